@@ -1,3 +1,6 @@
+// Grid indexing is written out as `x + y * W` even for zero terms.
+#![allow(clippy::identity_op, clippy::erasing_op)]
+
 use navni::prelude::*;
 
 async fn amain() {
@@ -71,24 +74,19 @@ async fn amain() {
             }
         }
 
-        match navni::mouse_state() {
-            MouseState::Drag(pos, _, MouseButton::Left) => {
-                if pos[0] >= 0
-                    && pos[1] >= 0
-                    && pos[0] < W as i32
-                    && pos[1] < H as i32
-                {
-                    buf[pos[0] as usize + W * pos[1] as usize] = CharCell::new(
-                        ' ',
-                        X256Color::FOREGROUND,
-                        X256Color::FUCHSIA,
-                    );
-                }
-            }
-            MouseState::Release(_, _, MouseButton::Right) => {
-                break;
-            }
-            _ => {}
+        let mouse = navni::mouse_state();
+        let pos = mouse.cursor_pos();
+        if mouse.is_down(MouseButton::Left)
+            && pos[0] >= 0
+            && pos[1] >= 0
+            && pos[0] < W as i32
+            && pos[1] < H as i32
+        {
+            buf[pos[0] as usize + W * pos[1] as usize] =
+                CharCell::new(' ', X256Color::FOREGROUND, X256Color::FUCHSIA);
+        }
+        if mouse.release(MouseButton::Right).is_some() {
+            break;
         }
 
         navni::draw_chars(W as u32, H as u32, &buf).await;
@@ -137,16 +137,18 @@ async fn amain() {
     // been spawned.
     let doom = NavniDoom::new(max_w, max_h, screen.clone(), tx_update, rx_keys);
     thread::spawn(move || {
-        init(doom);
+        init(&std::env::args().collect::<Vec<_>>(), doom);
     });
 
     let mut pressed_keys = Vec::new();
 
-    while let Ok(_) = rx_update.recv() {
-        {
+    while rx_update.recv().is_ok() {
+        // Don't hold the lock across the await.
+        let frame = {
             let screen = screen.lock().unwrap();
-            navni::draw_pixels(screen.w, screen.h, &screen.buf).await;
-        }
+            navni::draw_pixels(screen.w, screen.h, &screen.buf)
+        };
+        frame.await;
 
         if navni::keypress().is("C-c") {
             return;
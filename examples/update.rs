@@ -2,16 +2,14 @@ use navni::prelude::*;
 
 fn main() {
     navni::run("update speed test", async {
-        let mut frame_counter = if std::env::var("vblank_mode")
-            .unwrap_or(Default::default())
-            == "0"
-        {
-            // If vblank is disabled, run as fast as possible.
-            FrameCounter::new(0.0)
-        } else {
-            // Run at 60 FPS normally.
-            FrameCounter::new(1.0 / 60.0)
-        };
+        let mut frame_counter =
+            if std::env::var("vblank_mode").unwrap_or_default() == "0" {
+                // If vblank is disabled, run as fast as possible.
+                FrameCounter::new(0.0)
+            } else {
+                // Run at 60 FPS normally.
+                FrameCounter::new(1.0 / 60.0)
+            };
 
         let mut i = 0;
 
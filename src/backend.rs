@@ -1,51 +1,395 @@
-use std::future::Future;
+//! Backend interface and runtime dispatch to the active backend.
+//!
+//! A built-in backend is picked when it's first needed. An explicit
+//! `navni::select_backend` call takes precedence, then the `NAVNI_BACKEND`
+//! environment variable (`gui`, `tty` or `headless`), and failing those the
+//! GUI backend is used if there seems to be a display to open a window on
+//! and the TTY backend otherwise.
+use std::{cell::RefCell, rc::Rc, sync::OnceLock};
 
-use crate::{FontSheet, FrameFuture, KeyTyped, prelude::*};
+use crate::{
+    BackgroundPolicy, Executor, FontSheet, KeyTyped, PixelMode, RunConfig,
+    TimedEvent, prelude::*,
+};
 
-pub fn run(_window_title: &str, _amain: impl Future<Output = ()> + 'static) {
-    panic!("Please compile with --features=gui or --features=tty");
+/// Display and input implementation that navni applications run on.
+///
+/// The built-in GUI, TTY and headless backends implement this trait, and
+/// applications can provide their own implementation to
+/// `navni::run_with_backend`. The methods correspond to the navni functions
+/// with the same names, see those for the expected behavior.
+///
+/// The backend is called from inside the application future while its
+/// `run` method is executing, so all methods take `&self` and
+/// implementations need interior mutability for their state.
+pub trait Backend {
+    /// Run the application until it exits.
+    ///
+    /// The backend should call `executor.poll()` once per frame and process
+    /// input in between the calls. The method should return when the poll
+    /// reports that the application has finished.
+    fn run(&self, config: &RunConfig, executor: Executor);
+
+    /// Switch between fullscreen and windowed mode.
+    fn set_fullscreen(&self, _fullscreen: bool) {}
+
+    /// Resize the window.
+    fn set_window_size(&self, _w: u32, _h: u32) {}
+
+    /// Change the window title.
+    fn set_window_title(&self, _title: &str) {}
+
+    /// Show or hide the mouse cursor.
+    fn show_cursor(&self, _show: bool) {}
+
+    /// Turn mouse input on or off.
+    fn set_mouse_capture(&self, _capture: bool) {}
+
+    /// Set the longest time in seconds between multi-click presses.
+    fn set_click_interval(&self, _seconds: f64) {}
+
+    /// Set a custom bitmap font sheet.
+    fn set_font(&self, _sheet: &FontSheet) {}
+
+    /// Set the system color palette.
+    fn set_palette(&self, _palette: &[Rgba; 16]) {}
+
+    /// Draw a pixel buffer for the current frame.
+    fn draw_pixels(&self, w: u32, h: u32, buffer: &[Rgba]);
+
+    /// Draw a character buffer for the current frame.
+    fn draw_chars(&self, w: u32, h: u32, buffer: &[CharCell]);
+
+    /// Set how pixels are shown with characters on a text terminal.
+    fn set_pixel_mode(&self, _mode: PixelMode) {}
+
+    /// Return pixel resolution of the display.
+    fn pixel_resolution(&self) -> (u32, u32);
+
+    /// Return char cell resolution of the display.
+    fn char_resolution(&self, max_w: u32, max_h: u32) -> (u32, u32);
+
+    /// Return current time in seconds.
+    fn now(&self) -> f64;
+
+    /// Sleep for given number of seconds.
+    fn sleep(&self, seconds: f64) {
+        std::thread::sleep(std::time::Duration::from_secs_f64(seconds));
+    }
+
+    /// Return if given key is currently held down.
+    fn is_down(&self, _key: Key) -> bool {
+        false
+    }
+
+    /// Return keypress from last frame.
+    fn keypress(&self) -> KeyTyped;
+
+    /// Return mouse action state from last frame.
+    fn mouse_state(&self) -> MouseState {
+        Default::default()
+    }
+
+    /// Return input events from last frame.
+    fn events(&self) -> Vec<TimedEvent> {
+        Vec::new()
+    }
+
+    /// Return whether the window has input focus.
+    fn is_focused(&self) -> bool {
+        true
+    }
+
+    /// Return whether the window is minimized.
+    fn is_minimized(&self) -> bool {
+        false
+    }
+
+    /// Set what the application does while it's in the background.
+    fn set_background_policy(&self, _policy: BackgroundPolicy) {}
+
+    /// Return the clipboard contents.
+    fn clipboard_get(&self) -> Option<String> {
+        None
+    }
+
+    /// Set the clipboard contents.
+    fn clipboard_set(&self, _text: &str) {}
+
+    /// Return which kind of backend this is.
+    ///
+    /// Custom backends should report the built-in type they behave the most
+    /// like.
+    fn backend_type(&self) -> BackendType;
 }
 
-pub fn set_font(_sheet: &FontSheet) {}
+/// Built-in backend choice, shared by all threads.
+static SELECTED: OnceLock<BackendType> = OnceLock::new();
 
-pub fn set_palette(_palette: &[Rgba; 16]) {}
+thread_local! {
+    static ACTIVE: RefCell<Option<Rc<dyn Backend>>> = const {
+        RefCell::new(None)
+    };
+}
 
-pub fn draw_pixels(_w: u32, _h: u32, _buffer: &[Rgba]) -> FrameFuture {
-    unimplemented!()
+pub fn is_compiled(backend: BackendType) -> bool {
+    match backend {
+        BackendType::Gui => cfg!(feature = "gui"),
+        BackendType::Tty => cfg!(feature = "tty"),
+        BackendType::Headless => cfg!(feature = "headless"),
+    }
 }
 
-pub fn draw_chars(_w: u32, _h: u32, _buffer: &[CharCell]) -> FrameFuture {
-    unimplemented!()
+pub fn select(backend: BackendType) {
+    assert!(
+        is_compiled(backend),
+        "Backend {backend:?} was not compiled in, enable its feature flag"
+    );
+    if let Some(active) = ACTIVE.with(|a| a.borrow().clone()) {
+        let active = active.backend_type();
+        assert_eq!(
+            active, backend,
+            "Backend {active:?} is already active, can't select {backend:?}"
+        );
+    }
+    let chosen = *SELECTED.get_or_init(|| backend);
+    assert_eq!(
+        chosen, backend,
+        "Backend {chosen:?} was already chosen, can't select {backend:?}"
+    );
 }
 
-pub fn pixel_resolution() -> (u32, u32) {
-    unimplemented!()
+/// Return the type of the active backend, or of the built-in backend that
+/// will be used if no backend has been activated yet.
+pub fn backend_type() -> BackendType {
+    if let Some(backend) = ACTIVE.with(|a| a.borrow().clone()) {
+        return backend.backend_type();
+    }
+    selected()
 }
 
-pub fn char_resolution(_max_w: u32, _max_h: u32) -> (u32, u32) {
-    unimplemented!()
+/// Make a backend the one navni calls go to.
+pub fn activate(backend: Rc<dyn Backend>) {
+    ACTIVE.with(|a| *a.borrow_mut() = Some(backend));
 }
 
-pub fn now() -> f64 {
-    unimplemented!()
+/// Call a function on the active backend.
+pub fn with<T>(f: impl FnOnce(&dyn Backend) -> T) -> T {
+    let backend = match ACTIVE.with(|a| a.borrow().clone()) {
+        Some(backend) => backend,
+        None => {
+            let backend = builtin();
+            activate(backend.clone());
+            backend
+        }
+    };
+    f(&*backend)
 }
 
-pub fn sleep(_seconds: f64) {
-    unimplemented!()
+/// Instantiate the selected built-in backend.
+pub fn builtin() -> Rc<dyn Backend> {
+    match selected() {
+        #[cfg(feature = "gui")]
+        BackendType::Gui => Rc::new(crate::gui::Gui),
+        #[cfg(feature = "tty")]
+        BackendType::Tty => Rc::new(crate::tty::Tty),
+        #[cfg(feature = "headless")]
+        BackendType::Headless => Rc::new(crate::headless::Headless),
+        #[allow(unreachable_patterns)]
+        _ => unreachable!(),
+    }
 }
 
-pub fn is_down(_key: Key) -> bool {
-    unimplemented!()
+fn selected() -> BackendType {
+    *SELECTED.get_or_init(|| from_env().unwrap_or_else(probe))
 }
 
-pub fn keypress() -> KeyTyped {
-    unimplemented!()
+fn from_env() -> Option<BackendType> {
+    let var = std::env::var("NAVNI_BACKEND").ok()?;
+    let backend = match var.to_lowercase().as_str() {
+        "gui" => BackendType::Gui,
+        "tty" => BackendType::Tty,
+        "headless" => BackendType::Headless,
+        _ => {
+            log::warn!("Unknown NAVNI_BACKEND value {var:?}");
+            return None;
+        }
+    };
+
+    if !is_compiled(backend) {
+        log::warn!("NAVNI_BACKEND={var} requested but not compiled in");
+        return None;
+    }
+
+    Some(backend)
 }
 
-pub fn mouse_state() -> MouseState {
-    unimplemented!()
+fn probe() -> BackendType {
+    let has_gui = is_compiled(BackendType::Gui);
+    let has_tty = is_compiled(BackendType::Tty);
+
+    if has_gui && (!has_tty || has_display()) {
+        BackendType::Gui
+    } else if has_tty {
+        BackendType::Tty
+    } else if is_compiled(BackendType::Headless) {
+        BackendType::Headless
+    } else {
+        panic!("Please compile with --features=gui or --features=tty");
+    }
 }
 
-pub fn backend_type() -> BackendType {
-    unimplemented!()
+/// Guess whether there's a graphical display available.
+fn has_display() -> bool {
+    if cfg!(any(
+        target_os = "windows",
+        target_os = "macos",
+        target_arch = "wasm32"
+    )) {
+        return true;
+    }
+
+    ["DISPLAY", "WAYLAND_DISPLAY"]
+        .iter()
+        .any(|var| std::env::var_os(var).is_some_and(|v| !v.is_empty()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::{Cell, RefCell};
+
+    use super::*;
+    use crate::KeyMods;
+
+    /// Minimal custom backend that counts the frames it has run.
+    #[derive(Default)]
+    struct Counter {
+        frames: Cell<u32>,
+    }
+
+    impl Backend for Counter {
+        fn run(&self, _config: &RunConfig, mut executor: Executor) {
+            while executor.poll().is_pending() {
+                self.frames.set(self.frames.get() + 1);
+            }
+        }
+
+        fn draw_pixels(&self, _w: u32, _h: u32, _buffer: &[Rgba]) {}
+
+        fn draw_chars(&self, _w: u32, _h: u32, _buffer: &[CharCell]) {}
+
+        fn pixel_resolution(&self) -> (u32, u32) {
+            (320, 200)
+        }
+
+        fn char_resolution(&self, _max_w: u32, _max_h: u32) -> (u32, u32) {
+            (40, 25)
+        }
+
+        fn now(&self) -> f64 {
+            self.frames.get() as f64
+        }
+
+        fn keypress(&self) -> KeyTyped {
+            Default::default()
+        }
+
+        fn backend_type(&self) -> BackendType {
+            BackendType::Tty
+        }
+    }
+
+    /// Custom backend that clicks the left mouse button on every frame.
+    #[derive(Default)]
+    struct Clicker {
+        frames: Cell<u32>,
+        mouse_state: RefCell<MouseState>,
+    }
+
+    impl Backend for Clicker {
+        fn run(&self, _config: &RunConfig, mut executor: Executor) {
+            while executor.poll().is_pending() {
+                self.frames.set(self.frames.get() + 1);
+                let mut mouse = self.mouse_state.borrow_mut();
+                mouse.frame_update();
+                *mouse.cursor_pos_mut() = [12, 3];
+                mouse.button_down(
+                    MouseButton::Left,
+                    KeyMods::from_held(&[Key::Ctrl].into()),
+                    self.now(),
+                    MouseState::DEFAULT_CLICK_INTERVAL,
+                );
+                mouse.button_up(MouseButton::Left);
+                mouse.scroll(0, 1);
+            }
+        }
+
+        fn draw_pixels(&self, _w: u32, _h: u32, _buffer: &[Rgba]) {}
+
+        fn draw_chars(&self, _w: u32, _h: u32, _buffer: &[CharCell]) {}
+
+        fn pixel_resolution(&self) -> (u32, u32) {
+            (320, 200)
+        }
+
+        fn char_resolution(&self, _max_w: u32, _max_h: u32) -> (u32, u32) {
+            (40, 25)
+        }
+
+        fn now(&self) -> f64 {
+            self.frames.get() as f64 * 0.1
+        }
+
+        fn keypress(&self) -> KeyTyped {
+            Default::default()
+        }
+
+        fn mouse_state(&self) -> MouseState {
+            *self.mouse_state.borrow()
+        }
+
+        fn backend_type(&self) -> BackendType {
+            BackendType::Tty
+        }
+    }
+
+    #[cfg(feature = "headless")]
+    #[test]
+    fn global_selection() {
+        crate::select_backend(BackendType::Headless);
+        crate::select_backend(BackendType::Headless);
+        let other = std::thread::spawn(crate::backend_type).join().unwrap();
+        assert_eq!(other, BackendType::Headless);
+    }
+
+    #[test]
+    fn custom_backend() {
+        crate::run_with_backend(Counter::default(), "test", async {
+            assert_eq!(crate::char_resolution(0, 0), (40, 25));
+            assert_eq!(crate::backend_type(), BackendType::Tty);
+
+            for _ in 0..5 {
+                crate::draw_chars(0, 0, &[]).await;
+            }
+            assert_eq!(crate::now(), 5.0);
+        });
+    }
+
+    #[test]
+    fn custom_backend_mouse() {
+        crate::run_with_backend(Clicker::default(), "test", async {
+            crate::draw_chars(0, 0, &[]).await;
+            let mouse = crate::mouse_state();
+            assert_eq!(mouse.cursor_pos(), [12, 3]);
+            assert_eq!(mouse.scroll_delta(), [0, 1]);
+            let click = mouse.release(MouseButton::Left).unwrap();
+            assert_eq!(click.pos, [12, 3]);
+            assert!(click.mods.ctrl);
+            assert_eq!(click.count, 1);
+
+            crate::draw_chars(0, 0, &[]).await;
+            let click = crate::mouse_state().release(MouseButton::Left);
+            assert_eq!(click.unwrap().count, 2);
+        });
+    }
 }
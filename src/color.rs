@@ -295,6 +295,15 @@ impl X256Color {
         #(x256_to_rgba(N),)*
     ];
     });
+
+    /// Convert to RGB using the given colors for the 16 system colors.
+    pub fn resolve(self, system_colors: &[Rgba; 16]) -> Rgba {
+        if self.0 < 16 {
+            system_colors[self.0 as usize]
+        } else {
+            self.into()
+        }
+    }
 }
 
 impl From<Rgba> for X256Color {
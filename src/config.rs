@@ -80,3 +80,30 @@ impl Default for FontSheet {
         }
     }
 }
+
+impl FontSheet {
+    /// Pixel size of a single glyph in the sheet.
+    ///
+    /// Panics if the sheet image dimensions aren't a multiple of 16.
+    pub fn glyph_size(&self) -> (u32, u32) {
+        assert!(
+            self.image.width().is_multiple_of(16)
+                && self.image.height().is_multiple_of(16),
+            "Font sheet dimensions aren't a multiple of 16, sheet must be a 16x16 grid"
+        );
+        (self.image.width() / 16, self.image.height() / 16)
+    }
+
+    /// Build a lookup table from UTF-16 codepoints to glyph indices.
+    ///
+    /// All codepoints without a glyph point to 0xff.
+    pub(crate) fn char_lookup(&self) -> Vec<u8> {
+        let mut ret = vec![0xff; 0x10000];
+        for (i, &c) in self.chars.iter().enumerate() {
+            if (c as u32) < 0x10000 {
+                ret[c as usize] = i as u8;
+            }
+        }
+        ret
+    }
+}
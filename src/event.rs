@@ -1,4 +1,4 @@
-use std::{fmt, str::FromStr};
+use std::{collections::HashSet, fmt, hash::BuildHasher, str::FromStr};
 
 use anyhow::anyhow;
 use serde_with::{DeserializeFromStr, SerializeDisplay};
@@ -113,6 +113,21 @@ impl KeyTyped {
         self.mods
     }
 
+    /// Return the character this keypress types as text input.
+    ///
+    /// Ctrl and Alt chords are commands, not text, on every backend. Keypad
+    /// keys type their character.
+    pub(crate) fn text(&self) -> Option<char> {
+        match self.key {
+            Key::Char(c) | Key::Keypad(c)
+                if !self.mods.ctrl && !self.mods.alt =>
+            {
+                Some(c)
+            }
+            _ => None,
+        }
+    }
+
     pub fn is(&self, code: &str) -> bool {
         let Ok(other) = code.parse::<Self>() else {
             return false;
@@ -147,6 +162,18 @@ pub struct KeyMods {
     pub logo: bool,
 }
 
+impl KeyMods {
+    /// Modifiers from the held down modifier keys.
+    pub fn from_held<S: BuildHasher>(key_down: &HashSet<Key, S>) -> Self {
+        KeyMods {
+            shift: key_down.contains(&Key::Shift),
+            ctrl: key_down.contains(&Key::Ctrl),
+            alt: key_down.contains(&Key::Alt),
+            logo: key_down.contains(&Key::Icon),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default, Debug)]
 pub enum Key {
     #[default]
@@ -169,12 +196,81 @@ pub enum Key {
     Ctrl,
     Alt,
     Icon,
+    CapsLock,
+    NumLock,
+    ScrollLock,
+    PrintScreen,
+    Pause,
+    Menu,
     /// Function key
     F(u8),
+    /// Keypad key, the char is a digit or one of `.`, `/`, `*`, `-`, `+`
+    /// and `=`. Keypad enter is reported as `Enter`.
+    Keypad(char),
+    /// Multimedia key
+    Media(MediaKey),
     /// Normal printable character
     Char(char),
 }
 
+/// Multimedia keys.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum MediaKey {
+    Play,
+    Pause,
+    PlayPause,
+    Reverse,
+    Stop,
+    FastForward,
+    Rewind,
+    Next,
+    Previous,
+    Record,
+    VolumeDown,
+    VolumeUp,
+    Mute,
+}
+
+impl MediaKey {
+    const ALL: [MediaKey; 13] = [
+        MediaKey::Play,
+        MediaKey::Pause,
+        MediaKey::PlayPause,
+        MediaKey::Reverse,
+        MediaKey::Stop,
+        MediaKey::FastForward,
+        MediaKey::Rewind,
+        MediaKey::Next,
+        MediaKey::Previous,
+        MediaKey::Record,
+        MediaKey::VolumeDown,
+        MediaKey::VolumeUp,
+        MediaKey::Mute,
+    ];
+
+    /// Key name in the `Key` string format.
+    fn name(self) -> &'static str {
+        match self {
+            MediaKey::Play => "MediaPlay",
+            MediaKey::Pause => "MediaPause",
+            MediaKey::PlayPause => "MediaPlayPause",
+            MediaKey::Reverse => "MediaRev",
+            MediaKey::Stop => "MediaStop",
+            MediaKey::FastForward => "MediaFfwd",
+            MediaKey::Rewind => "MediaRew",
+            MediaKey::Next => "MediaNext",
+            MediaKey::Previous => "MediaPrev",
+            MediaKey::Record => "MediaRec",
+            MediaKey::VolumeDown => "VolDn",
+            MediaKey::VolumeUp => "VolUp",
+            MediaKey::Mute => "Mute",
+        }
+    }
+}
+
+/// Characters on keypad keys.
+pub(crate) const KEYPAD_CHARS: &str = "0123456789./*-+=";
+
 impl Key {
     pub fn is_printable(&self) -> bool {
         matches!(self, Key::Char(_))
@@ -184,7 +280,15 @@ impl Key {
     /// for a physical keyboard key.
     pub fn char_to_lowercase(&self) -> Key {
         match self {
-            Key::Char(c) => Key::Char(c.to_ascii_lowercase()),
+            Key::Char(c) => {
+                // Keep the char as is if its lowercase form is several chars,
+                // eg. for 'İ'.
+                let mut lower = c.to_lowercase();
+                match (lower.next(), lower.next()) {
+                    (Some(l), None) => Key::Char(l),
+                    _ => Key::Char(*c),
+                }
+            }
             a => *a,
         }
     }
@@ -216,9 +320,21 @@ impl fmt::Display for Key {
             Key::Ctrl => write!(f, "Ctrl"),
             Key::Alt => write!(f, "Alt"),
             Key::Icon => write!(f, "Icon"),
+            Key::CapsLock => write!(f, "Caps"),
+            Key::NumLock => write!(f, "NumLk"),
+            Key::ScrollLock => write!(f, "ScrLk"),
+            Key::PrintScreen => write!(f, "PrtSc"),
+            Key::Pause => write!(f, "Pause"),
+            Key::Menu => write!(f, "Menu"),
             Key::F(n) => write!(f, "F{n}"),
+            Key::Keypad(c) => write!(f, "KP{c}"),
+            Key::Media(m) => write!(f, "{}", m.name()),
             // NB. Space is printable but not serializable
             Key::Char(' ') => write!(f, "Sp"),
+            // Other invisible chars are written as code points.
+            Key::Char(c) if !is_visible(*c) => {
+                write!(f, "U+{:04X}", *c as u32)
+            }
             Key::Char(c) => write!(f, "{c}"),
         }
     }
@@ -228,10 +344,10 @@ impl FromStr for Key {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() == 1 {
-            let c = s.as_bytes()[0];
-            if (33..127).contains(&c) {
-                Ok(Key::Char(s.chars().next().unwrap()))
+        let mut chars = s.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if is_visible(c) {
+                Ok(Key::Char(c))
             } else {
                 Err(anyhow!("Bad key {s:?}"))
             }
@@ -256,25 +372,60 @@ impl FromStr for Key {
                 "Ctrl" => Ok(Key::Ctrl),
                 "Alt" => Ok(Key::Alt),
                 "Icon" => Ok(Key::Icon),
-                "F1" => Ok(Key::F(1)),
-                "F2" => Ok(Key::F(2)),
-                "F3" => Ok(Key::F(3)),
-                "F4" => Ok(Key::F(4)),
-                "F5" => Ok(Key::F(5)),
-                "F6" => Ok(Key::F(6)),
-                "F7" => Ok(Key::F(7)),
-                "F8" => Ok(Key::F(8)),
-                "F9" => Ok(Key::F(9)),
-                "F10" => Ok(Key::F(10)),
-                "F11" => Ok(Key::F(11)),
-                "F12" => Ok(Key::F(12)),
+                "Caps" => Ok(Key::CapsLock),
+                "NumLk" => Ok(Key::NumLock),
+                "ScrLk" => Ok(Key::ScrollLock),
+                "PrtSc" => Ok(Key::PrintScreen),
+                "Pause" => Ok(Key::Pause),
+                "Menu" => Ok(Key::Menu),
                 "Sp" => Ok(Key::Char(' ')),
-                _ => Err(anyhow!("Bad key {s:?}")),
+                _ => {
+                    // Function keys, with no leading zeroes.
+                    if let Some(n) = s.strip_prefix('F')
+                        && !n.starts_with('0')
+                        && n.bytes().all(|b| b.is_ascii_digit())
+                        && let Ok(n) = n.parse::<u8>()
+                        && (1..=35).contains(&n)
+                    {
+                        return Ok(Key::F(n));
+                    }
+
+                    if let Some(c) = s.strip_prefix("KP")
+                        && c.chars().count() == 1
+                        && KEYPAD_CHARS.contains(c)
+                    {
+                        return Ok(Key::Keypad(c.chars().next().unwrap()));
+                    }
+
+                    if let Some(m) =
+                        MediaKey::ALL.iter().find(|m| m.name() == s)
+                    {
+                        return Ok(Key::Media(*m));
+                    }
+
+                    // Any char as a Unicode code point, eg. "U+002D".
+                    if let Some(n) = s.strip_prefix("U+")
+                        && (1..=6).contains(&n.len())
+                        && n.bytes().all(|b| b.is_ascii_hexdigit())
+                        && let Some(c) = u32::from_str_radix(n, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                    {
+                        return Ok(Key::Char(c));
+                    }
+
+                    Err(anyhow!("Bad key {s:?}"))
+                }
             }
         }
     }
 }
 
+/// Return whether a char can stand for itself in the key string format.
+fn is_visible(c: char) -> bool {
+    !c.is_whitespace() && !c.is_control()
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub enum MouseButton {
     Left,
@@ -282,120 +433,243 @@ pub enum MouseButton {
     Right,
 }
 
-/// Complex mouse state for IMGUI
-#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
-pub enum MouseState {
-    /// Mouse hovering over position with buttons unpressed.
-    Hover([i32; 2]),
-    /// `Drag(p, s, b)` Dragged from `s` to `p` with button `b down.
-    Drag([i32; 2], [i32; 2], MouseButton),
-    /// `Release(p, s, b)` Button `b` released over `p` after drag from `s`.
-    Release([i32; 2], [i32; 2], MouseButton),
-    /// `Scroll(p, [u, v])` Mouse at position p scrolled `u` horizontal, `v`
-    /// vertical.
-    Scroll([i32; 2], [i32; 2]),
+impl MouseButton {
+    pub const ALL: [MouseButton; 3] =
+        [MouseButton::Left, MouseButton::Middle, MouseButton::Right];
 }
-use MouseState::*;
 
-impl Default for MouseState {
-    fn default() -> Self {
-        Hover(Default::default())
+/// How far apart in cursor coordinates successive presses can be and still
+/// count as a multi-click.
+const CLICK_SLOP: i32 = 2;
+
+/// Mouse button press.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Click {
+    /// Position where the button was pressed down.
+    pub pos: [i32; 2],
+    pub button: MouseButton,
+    /// Modifier keys held when the button was pressed.
+    pub mods: KeyMods,
+    /// Number of presses in quick succession, 2 for a double click and 3 for
+    /// a triple click.
+    pub count: u32,
+    /// Time of the press in `navni::now` seconds.
+    pub time: f64,
+}
+
+impl Click {
+    /// Return whether `next` continues a multi-click started by this press.
+    fn is_followed_by(&self, next: &Click, interval: f64) -> bool {
+        self.button == next.button
+            && next.time - self.time <= interval
+            && (self.pos[0] - next.pos[0]).abs() <= CLICK_SLOP
+            && (self.pos[1] - next.pos[1]).abs() <= CLICK_SLOP
     }
 }
 
+/// State of a single mouse button.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub enum ButtonState {
+    #[default]
+    Up,
+    /// Button is held down.
+    Down(Click),
+    /// Button was released since the previous frame.
+    Released(Click),
+}
+
+impl ButtonState {
+    pub fn click(&self) -> Option<Click> {
+        match self {
+            ButtonState::Up => None,
+            ButtonState::Down(c) | ButtonState::Released(c) => Some(*c),
+        }
+    }
+}
+
+/// Complex mouse state for IMGUI
+///
+/// Tracks every button separately, so eg. a right button drag can start
+/// while the left button is held down.
+///
+/// Backends update the state with the mutator methods as input comes in and
+/// call `frame_update` between frames.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct MouseState {
+    pub(crate) pos: [i32; 2],
+    pub(crate) buttons: [ButtonState; 3],
+    pub(crate) scroll: [i32; 2],
+    // Most recent press, for counting multi-clicks.
+    pub(crate) last_press: Option<Click>,
+    // Button changes held back until the next frame so that a release
+    // isn't overwritten by another press during the same frame.
+    pub(crate) pending: [Option<ButtonState>; 3],
+}
+
 impl From<MouseState> for [i32; 2] {
     fn from(value: MouseState) -> Self {
         value.cursor_pos()
     }
 }
 
-#[allow(dead_code)]
 impl MouseState {
+    /// Longest time in seconds between multi-click presses unless changed
+    /// with `navni::set_click_interval`.
+    pub const DEFAULT_CLICK_INTERVAL: f64 = 0.5;
+
     /// Return current mouse cursor position.
     pub fn cursor_pos(&self) -> [i32; 2] {
-        match self {
-            Hover(p) => *p,
-            Drag(p, _, _) => *p,
-            Release(p, _, _) => *p,
-            Scroll(p, _) => *p,
-        }
+        self.pos
     }
 
-    pub(crate) fn cursor_pos_mut(&mut self) -> &mut [i32; 2] {
-        match self {
-            Hover(p) => p,
-            Drag(p, _, _) => p,
-            Release(p, _, _) => p,
-            Scroll(p, _) => p,
+    /// Backend API, move the cursor.
+    pub fn cursor_pos_mut(&mut self) -> &mut [i32; 2] {
+        &mut self.pos
+    }
+
+    pub fn button(&self, button: MouseButton) -> ButtonState {
+        self.buttons[button as usize]
+    }
+
+    /// Return whether the button is held down.
+    pub fn is_down(&self, button: MouseButton) -> bool {
+        matches!(self.button(button), ButtonState::Down(_))
+    }
+
+    /// Return the press if the button is being held down and dragged.
+    pub fn drag(&self, button: MouseButton) -> Option<Click> {
+        match self.button(button) {
+            ButtonState::Down(c) => Some(c),
+            _ => None,
         }
     }
 
-    /// Return position where mouse button was first pressed down for `Drag`
-    /// and `Release` states.
-    pub fn press_pos(&self) -> Option<[i32; 2]> {
-        match self {
-            Hover(_) => None,
-            Drag(_, s, _) => Some(*s),
-            Release(_, s, _) => Some(*s),
-            Scroll(_, _) => None,
+    /// Return the press if the button was released since the previous
+    /// frame.
+    pub fn release(&self, button: MouseButton) -> Option<Click> {
+        match self.button(button) {
+            ButtonState::Released(c) => Some(c),
+            _ => None,
         }
     }
 
+    /// Return the first released button's press, if any.
+    pub fn any_release(&self) -> Option<Click> {
+        MouseButton::ALL.iter().find_map(|&b| self.release(b))
+    }
+
+    /// Return whether no buttons are down or released and the wheel was not
+    /// scrolled.
+    pub fn is_hover(&self) -> bool {
+        self.scroll == [0, 0]
+            && self.buttons.iter().all(|b| *b == ButtonState::Up)
+    }
+
     pub fn scroll_delta(&self) -> [i32; 2] {
-        if let Scroll(_, z) = self { *z } else { [0, 0] }
+        self.scroll
     }
 
-    pub(crate) fn button_down(&mut self, button: MouseButton) {
-        match self {
-            Hover(p) => *self = Drag(*p, *p, button),
-            // Ignore new button presses when in pressed state.
-            Drag(_, _, _) => {}
-            // XXX: Also ignore new presses in release state because we don't
-            // want to clobber the release state before the frame is over.
-            // This can lead to actually missing input events if the user is
-            // releasing and pressing buttons in very quick succession.
-            Release(_, _, _) => {}
-            Scroll(p, _) => *self = Drag(*p, *p, button),
+    /// Backend API, press a button at the cursor position.
+    ///
+    /// `now` is the time of the press and `click_interval` the longest time
+    /// since the previous press for counting a multi-click.
+    pub fn button_down(
+        &mut self,
+        button: MouseButton,
+        mods: KeyMods,
+        now: f64,
+        click_interval: f64,
+    ) {
+        let mut click = Click {
+            pos: self.pos,
+            button,
+            mods,
+            count: 1,
+            time: now,
+        };
+        if let Some(prev) = self.last_press
+            && prev.is_followed_by(&click, click_interval)
+        {
+            click.count = prev.count + 1;
+        }
+        self.last_press = Some(click);
+
+        let i = button as usize;
+        if matches!(self.buttons[i], ButtonState::Released(_))
+            || self.pending[i].is_some()
+        {
+            // The button was already released during this frame, show the
+            // new press on the next one. A third press in the same frame
+            // replaces the second, but keeps counting the multi-click.
+            self.pending[i] = Some(ButtonState::Down(click));
+        } else {
+            self.buttons[i] = ButtonState::Down(click);
         }
     }
 
-    pub(crate) fn button_up(&mut self, button: MouseButton) {
-        match self {
-            Drag(p, s, b) if button == *b => *self = Release(*p, *s, *b),
-            _ => {}
+    /// Backend API, release a button.
+    pub fn button_up(&mut self, button: MouseButton) {
+        let i = button as usize;
+        let state = match &mut self.pending[i] {
+            Some(state) => state,
+            None => &mut self.buttons[i],
+        };
+        if let ButtonState::Down(c) = *state {
+            *state = ButtonState::Released(c);
         }
     }
 
-    pub(crate) fn scroll(&mut self, u: i32, v: i32) {
+    /// Backend API, scroll the wheel by one step along either axis.
+    pub fn scroll(&mut self, u: i32, v: i32) {
         debug_assert!(u.abs() == 1 || v.abs() == 1);
-        let p = self.cursor_pos();
-        *self = Scroll(p, [u, v]);
+        self.scroll = [self.scroll[0] + u, self.scroll[1] + v];
     }
 
-    /// Update called every frame, exits transient `Release` and `Scroll`
-    /// states.
-    pub(crate) fn frame_update(&mut self) {
-        if let Release(p, _, _) | Scroll(p, _) = self {
-            *self = Hover(*p);
+    /// Backend API, drop any button or scroll state and keep just the
+    /// cursor position.
+    pub fn release_all(&mut self) {
+        *self = MouseState {
+            pos: self.pos,
+            ..Default::default()
+        };
+    }
+
+    /// Backend API, update called every frame, exits transient released and
+    /// scroll states and applies presses held back from the last frame.
+    pub fn frame_update(&mut self) {
+        for (b, pending) in self.buttons.iter_mut().zip(&mut self.pending) {
+            if let ButtonState::Released(_) = b {
+                *b = ButtonState::Up;
+            }
+            if let Some(state) = pending.take() {
+                *b = state;
+            }
         }
+        self.scroll = [0, 0];
     }
 }
 
 impl<T: Into<[i32; 2]>> std::ops::AddAssign<T> for MouseState {
     fn add_assign(&mut self, rhs: T) {
         let [dx, dy] = rhs.into();
-        match self {
-            Hover([x, y]) | Scroll([x, y], _) => {
-                *x += dx;
-                *y += dy;
-            }
-            Drag([x1, y1], [x2, y2], _) | Release([x1, y1], [x2, y2], _) => {
-                *x1 += dx;
-                *x2 += dx;
-                *y1 += dy;
-                *y2 += dy;
+        let translate = |[x, y]: &mut [i32; 2]| {
+            *x += dx;
+            *y += dy;
+        };
+
+        translate(&mut self.pos);
+        for b in self
+            .buttons
+            .iter_mut()
+            .chain(self.pending.iter_mut().flatten())
+        {
+            if let ButtonState::Down(c) | ButtonState::Released(c) = b {
+                translate(&mut c.pos);
             }
         }
+        if let Some(c) = &mut self.last_press {
+            translate(&mut c.pos);
+        }
     }
 }
 
@@ -406,6 +680,73 @@ impl<T: Into<[i32; 2]>> std::ops::SubAssign<T> for MouseState {
     }
 }
 
+/// Input event from the application window.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Event {
+    /// Key was pressed down.
+    ///
+    /// Letter keys are reported by the physical key in lowercase on
+    /// backends that know about physical keys, use `Text` to get the typed
+    /// characters.
+    KeyDown(KeyTyped),
+    /// Key held down is repeating.
+    KeyRepeat(KeyTyped),
+    /// Key was released.
+    ///
+    /// Not supported on TTY backends without progressive keyboard
+    /// enhancement.
+    KeyUp(KeyTyped),
+    /// Printable character was typed.
+    ///
+    /// Not reported for keys typed with Ctrl or Alt held down.
+    Text(char),
+    /// Mouse cursor moved to a new position.
+    MouseMove([i32; 2]),
+    /// Mouse button was pressed down at position.
+    MouseDown([i32; 2], MouseButton),
+    /// Mouse button was released at position.
+    MouseUp([i32; 2], MouseButton),
+    /// `Scroll(p, [u, v])` Mouse at position p scrolled `u` horizontal, `v`
+    /// vertical.
+    Scroll([i32; 2], [i32; 2]),
+    /// Window size changed, the pixel and char resolutions should be read
+    /// again.
+    Resize,
+    /// Window gained (`true`) or lost (`false`) input focus.
+    Focus(bool),
+    /// Window was minimized (`true`) or restored (`false`).
+    Minimize(bool),
+    /// Text was pasted into the window.
+    Paste(String),
+}
+
+/// Input event with the time it arrived.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TimedEvent {
+    /// Arrival time in the same seconds as `navni::now`.
+    pub time: f64,
+    pub event: Event,
+}
+
+impl Event {
+    /// Return whether this is a mouse event.
+    pub fn is_mouse(&self) -> bool {
+        matches!(
+            self,
+            Event::MouseMove(..)
+                | Event::MouseDown(..)
+                | Event::MouseUp(..)
+                | Event::Scroll(..)
+        )
+    }
+}
+
+impl TimedEvent {
+    pub fn new(time: f64, event: Event) -> Self {
+        TimedEvent { time, event }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -416,10 +757,15 @@ mod test {
         fn arbitrary(g: &mut Gen) -> Key {
             use Key::*;
 
-            // Printable ASCII chars 50 % of the time.
-            if bool::arbitrary(g) {
-                let n = ((u32::arbitrary(g) % 95) + 32) as u8;
-                return Key::Char(n as char);
+            // Printable ASCII chars 40 % of the time.
+            match u8::arbitrary(g) % 10 {
+                0..4 => {
+                    let n = ((u32::arbitrary(g) % 95) + 32) as u8;
+                    return Key::Char(n as char);
+                }
+                // Any Unicode char 10 % of the time.
+                4 => return Key::Char(char::arbitrary(g)),
+                _ => {}
             }
 
             // Special keys otherwise.
@@ -451,6 +797,18 @@ mod test {
                 F(10),
                 F(11),
                 F(12),
+                F(13),
+                F(24),
+                CapsLock,
+                NumLock,
+                ScrollLock,
+                PrintScreen,
+                Pause,
+                Menu,
+                Keypad('5'),
+                Keypad('+'),
+                Media(MediaKey::PlayPause),
+                Media(MediaKey::VolumeUp),
             ];
             *g.choose(vals).unwrap()
         }
@@ -487,27 +845,167 @@ mod test {
     }
 
     #[test]
-    fn mouse_translate() {
-        use MouseState::*;
+    fn extended_keys() {
+        assert_eq!("KP5".parse::<Key>().unwrap(), Key::Keypad('5'));
+        assert_eq!("F24".parse::<Key>().unwrap(), Key::F(24));
+        assert_eq!(
+            "VolUp".parse::<Key>().unwrap(),
+            Key::Media(MediaKey::VolumeUp)
+        );
+        assert!("C-KP-".parse::<KeyTyped>().is_ok());
+        assert!("S-Caps".parse::<KeyTyped>().is_ok());
+
+        for bad in [
+            "F0",
+            "F01",
+            "F+1",
+            "F36",
+            "KPx",
+            "KP55",
+            "Media",
+            "U+",
+            "U+D800",
+            "U+110000",
+            "U+0000041",
+            "\t",
+        ] {
+            assert!(bad.parse::<Key>().is_err(), "{bad}");
+        }
+    }
 
-        fn test(a: MouseState, c: MouseState) {
-            let mut b = a;
-            b += [10, 20];
-            assert_eq!(b, c);
+    #[test]
+    fn unicode_keys() {
+        for (s, c) in
+            [("ä", 'ä'), ("Ж", 'Ж'), ("U+002D", '-'), ("U+A0", '\u{a0}')]
+        {
+            assert_eq!(s.parse::<Key>().unwrap(), Key::Char(c));
         }
+        assert_eq!(Key::Char('\u{a0}').to_string(), "U+00A0");
+        assert_eq!(Key::Char('\t').to_string(), "U+0009");
+        assert_eq!("C-ö".parse::<KeyTyped>().unwrap().to_string(), "C-ö");
+        assert!("S-ö".parse::<KeyTyped>().is_err());
+
+        assert_eq!(Key::Char('Ä').char_to_lowercase(), Key::Char('ä'));
+        assert_eq!(Key::Char('Ж').char_to_lowercase(), Key::Char('ж'));
+        assert_eq!(Key::Char('İ').char_to_lowercase(), Key::Char('İ'));
+    }
 
-        test(Hover([10, 10]), Hover([20, 30]));
-        test(
-            Drag([10, 10], [20, 20], MouseButton::Left),
-            Drag([20, 30], [30, 40], MouseButton::Left),
+    #[test]
+    fn mouse_translate() {
+        let mut a = MouseState::default();
+        *a.cursor_pos_mut() = [10, 10];
+        a.button_down(
+            MouseButton::Left,
+            Default::default(),
+            0.0,
+            MouseState::DEFAULT_CLICK_INTERVAL,
         );
-        test(
-            Release([10, 10], [20, 20], MouseButton::Left),
-            Release([20, 30], [30, 40], MouseButton::Left),
+        a.scroll(1, 0);
+        a += [10, 20];
+
+        assert_eq!(a.cursor_pos(), [20, 30]);
+        assert_eq!(a.drag(MouseButton::Left).unwrap().pos, [20, 30]);
+        // NB. The scroll delta is not a screen position, it should not be
+        // translated.
+        assert_eq!(a.scroll_delta(), [1, 0]);
+    }
+
+    #[test]
+    fn mouse_buttons() {
+        use MouseButton::*;
+
+        let shift = KeyMods {
+            shift: true,
+            ..Default::default()
+        };
+        let mut m = MouseState::default();
+        m.button_down(Left, shift, 0.0, MouseState::DEFAULT_CLICK_INTERVAL);
+        m.button_down(
+            Right,
+            Default::default(),
+            0.1,
+            MouseState::DEFAULT_CLICK_INTERVAL,
         );
+        assert!(m.is_down(Left) && m.is_down(Right));
+        assert!(m.drag(Left).unwrap().mods.shift);
+
+        m.button_up(Right);
+        assert_eq!(m.release(Right).unwrap().time, 0.1);
+        assert!(m.is_down(Left));
+        m.frame_update();
+        assert_eq!(m.button(Right), ButtonState::Up);
+        assert!(m.is_down(Left));
+
+        m.button_up(Left);
+        m.frame_update();
+        assert!(m.is_hover());
+    }
+
+    #[test]
+    fn multi_click() {
+        use MouseButton::*;
+
+        let mut m = MouseState::default();
+        let click = |m: &mut MouseState, button, t| {
+            m.button_down(
+                button,
+                Default::default(),
+                t,
+                MouseState::DEFAULT_CLICK_INTERVAL,
+            );
+            m.button_up(button);
+            let count = m.release(button).unwrap().count;
+            m.frame_update();
+            count
+        };
+
+        assert_eq!(click(&mut m, Left, 0.0), 1);
+        assert_eq!(click(&mut m, Left, 0.2), 2);
+        assert_eq!(click(&mut m, Left, 0.4), 3);
+        // Too slow.
+        assert_eq!(click(&mut m, Left, 1.0), 1);
+        // Different button.
+        assert_eq!(click(&mut m, Right, 1.1), 1);
+        // Moved too far.
+        *m.cursor_pos_mut() = [10, 0];
+        assert_eq!(click(&mut m, Right, 1.2), 1);
+        assert_eq!(click(&mut m, Right, 1.3), 2);
+    }
+
+    #[test]
+    fn clicks_in_one_frame() {
+        use MouseButton::*;
+
+        let mut m = MouseState::default();
+        let press = |m: &mut MouseState, t| {
+            m.button_down(
+                Left,
+                Default::default(),
+                t,
+                MouseState::DEFAULT_CLICK_INTERVAL,
+            )
+        };
 
-        // NB. The second element in `Scroll` is the scroll delta, not a
-        // screen position. It should not be translated.
-        test(Scroll([10, 10], [1, 1]), Scroll([20, 30], [1, 1]));
+        // A whole double click arrives before the next frame.
+        press(&mut m, 0.0);
+        m.button_up(Left);
+        press(&mut m, 0.1);
+        m.button_up(Left);
+        assert_eq!(m.release(Left).unwrap().count, 1);
+        m.frame_update();
+        assert_eq!(m.release(Left).unwrap().count, 2);
+        m.frame_update();
+        assert!(m.is_hover());
+
+        // The second press of a double click starts a drag on the next
+        // frame.
+        press(&mut m, 1.0);
+        m.button_up(Left);
+        press(&mut m, 1.1);
+        assert_eq!(m.release(Left).unwrap().count, 1);
+        m.frame_update();
+        assert_eq!(m.drag(Left).unwrap().count, 2);
+        m.button_up(Left);
+        assert_eq!(m.release(Left).unwrap().count, 2);
     }
 }
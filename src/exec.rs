@@ -1,16 +1,40 @@
 use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
     future::Future,
     pin::Pin,
-    task::{Context, Poll, Waker},
+    rc::Rc,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Wake, Waker},
 };
 
+use rustc_hash::FxHashMap as HashMap;
+
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+thread_local! {
+    /// Number of the frame being run.
+    static FRAME: Cell<u64> = const { Cell::new(0) };
+
+    /// Wakers of tasks waiting for the next frame.
+    static NEXT_FRAME: RefCell<Vec<Waker>> = const { RefCell::new(Vec::new()) };
+
+    /// Tasks spawned since the executor last checked.
+    static SPAWNED: RefCell<Vec<(usize, Task)>> =
+        const { RefCell::new(Vec::new()) };
+
+    /// Identifier for the next spawned task.
+    static NEXT_ID: Cell<usize> = const { Cell::new(MAIN + 1) };
+}
+
 /// Future that will wait for the completion of the current engine rendering
 /// frame.
 ///
 /// Should be awaited on at the bottom of the game loop.
 #[derive(Default)]
 pub struct FrameFuture {
-    done: bool,
+    // Frame during which the future was first polled.
+    frame: Option<u64>,
 }
 
 impl Future for FrameFuture {
@@ -18,48 +42,301 @@ impl Future for FrameFuture {
 
     fn poll(
         mut self: Pin<&mut Self>,
-        _context: &mut Context,
+        context: &mut Context,
     ) -> Poll<Self::Output> {
-        if self.done {
-            // We were told to step, meaning this future gets destroyed and we run
-            // the main future until we call next_frame again and end up in this poll
-            // function again.
-            Poll::Ready(())
+        let current = FRAME.with(|a| a.get());
+        match self.frame {
+            // A frame has passed since we started waiting.
+            Some(frame) if frame < current => Poll::Ready(()),
+            _ => {
+                self.frame.get_or_insert(current);
+                // Get polled again when the next frame starts.
+                NEXT_FRAME
+                    .with(|a| a.borrow_mut().push(context.waker().clone()));
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Identifier of the main task.
+const MAIN: usize = 0;
+
+/// Start running a future as a separate task.
+///
+/// The task is polled alongside the main application future and runs until
+/// it finishes or the application exits, whether or not the returned handle
+/// is kept. A new task gets polled for the first time during the frame it
+/// was spawned on.
+pub fn spawn<T: 'static>(
+    future: impl Future<Output = T> + 'static,
+) -> JoinHandle<T> {
+    let state: Rc<RefCell<JoinState<T>>> = Default::default();
+
+    let task = {
+        let state = state.clone();
+        async move {
+            let ret = future.await;
+            let mut state = state.borrow_mut();
+            state.result = Some(ret);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    };
+
+    let id = NEXT_ID.with(|a| a.replace(a.get() + 1));
+    SPAWNED.with(|a| a.borrow_mut().push((id, Box::pin(task))));
+
+    JoinHandle { state }
+}
+
+struct JoinState<T> {
+    result: Option<T>,
+    waker: Option<Waker>,
+}
+
+impl<T> Default for JoinState<T> {
+    fn default() -> Self {
+        JoinState {
+            result: None,
+            waker: None,
+        }
+    }
+}
+
+/// Handle for awaiting the result of a task started with `spawn`.
+pub struct JoinHandle<T> {
+    state: Rc<RefCell<JoinState<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Return whether the task has finished.
+    ///
+    /// The result can still be read by awaiting the handle.
+    pub fn is_finished(&self) -> bool {
+        self.state.borrow().result.is_some()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut state = self.state.borrow_mut();
+        match state.result.take() {
+            Some(ret) => Poll::Ready(ret),
+            None => {
+                state.waker = Some(context.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Executor for the application's async main function.
+///
+/// Backends receive the executor in `Backend::run` and poll it once per
+/// frame.
+///
+/// Tasks are only polled after they have been woken. `FrameFuture` wakes
+/// its task when the next frame starts, other futures can wake their tasks
+/// at any time from any thread and the task gets polled on the next frame.
+pub struct Executor {
+    tasks: HashMap<usize, Task>,
+    ready: Arc<ReadyQueue>,
+}
+
+impl Executor {
+    pub(crate) fn new(amain: impl Future<Output = ()> + 'static) -> Self {
+        let ready: Arc<ReadyQueue> = Default::default();
+        // The main task starts out ready to run.
+        ready.push(MAIN);
+
+        let mut tasks: HashMap<usize, Task> = Default::default();
+        tasks.insert(MAIN, Box::pin(amain));
+
+        Executor { tasks, ready }
+    }
+
+    /// Run the application until it awaits the next frame.
+    ///
+    /// Returns `Poll::Ready` when the application has exited.
+    pub fn poll(&mut self) -> Poll<()> {
+        FRAME.with(|a| a.set(a.get() + 1));
+        for waker in NEXT_FRAME.with(|a| std::mem::take(&mut *a.borrow_mut())) {
+            waker.wake();
+        }
+
+        let mut ret = Poll::Pending;
+        // Only run tasks that were ready when the frame started and tasks
+        // spawned during the frame, tasks woken during the frame will run
+        // on the next one.
+        let mut queue = self.ready.take();
+        loop {
+            queue.extend(self.adopt_spawned());
+            let Some(id) = queue.pop_front() else {
+                break;
+            };
+            if self.poll_task(id).is_ready() && id == MAIN {
+                ret = Poll::Ready(());
+                break;
+            }
+        }
+
+        if ret.is_ready() {
+            // Don't leak leftover tasks into a later run on this thread.
+            SPAWNED.with(|a| a.borrow_mut().clear());
+            NEXT_FRAME.with(|a| a.borrow_mut().clear());
+            crate::record::stop();
         } else {
-            self.done = true;
-            Poll::Pending
+            crate::record::end_frame();
+        }
+
+        ret
+    }
+
+    /// Move newly spawned tasks into the executor and return their
+    /// identifiers.
+    fn adopt_spawned(&mut self) -> Vec<usize> {
+        SPAWNED.with(|a| {
+            a.borrow_mut()
+                .drain(..)
+                .map(|(id, task)| {
+                    self.tasks.insert(id, task);
+                    id
+                })
+                .collect()
+        })
+    }
+
+    fn poll_task(&mut self, id: usize) -> Poll<()> {
+        // Tasks can get woken after they have finished.
+        let Some(task) = self.tasks.get_mut(&id) else {
+            return Poll::Pending;
+        };
+
+        let waker = Waker::from(Arc::new(TaskWaker {
+            id,
+            ready: self.ready.clone(),
+        }));
+        let mut ctx = Context::from_waker(&waker);
+        let ret = task.as_mut().poll(&mut ctx);
+        if ret.is_ready() {
+            self.tasks.remove(&id);
         }
+        ret
     }
 }
 
-// Called from the conditionally compiled backends, marked as dead_code
-// here so backend-less compiles don't give a warning.
-#[allow(dead_code)]
-pub fn poll<T>(f: &mut Pin<Box<dyn Future<Output = T>>>) -> Option<T> {
-    let waker = waker();
-    let mut ctx = std::task::Context::from_waker(&waker);
-    match Pin::new(f).poll(&mut ctx) {
-        Poll::Pending => None,
-        Poll::Ready(val) => Some(val),
+/// Identifiers of tasks that have been woken.
+#[derive(Default)]
+struct ReadyQueue(Mutex<VecDeque<usize>>);
+
+impl ReadyQueue {
+    fn push(&self, id: usize) {
+        let mut queue = self.0.lock().unwrap();
+        if !queue.contains(&id) {
+            queue.push_back(id);
+        }
+    }
+
+    fn take(&self) -> VecDeque<usize> {
+        std::mem::take(&mut *self.0.lock().unwrap())
     }
 }
 
-fn waker() -> Waker {
-    use std::task::{RawWaker, RawWakerVTable};
-    unsafe fn clone(data: *const ()) -> RawWaker {
-        RawWaker::new(data, &VTABLE)
+struct TaskWaker {
+    id: usize,
+    ready: Arc<ReadyQueue>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
     }
-    unsafe fn wake(_data: *const ()) {
-        panic!("not supported")
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.ready.push(self.id);
     }
-    unsafe fn wake_by_ref(data: *const ()) {
-        unsafe { wake(data) }
+}
+
+#[cfg(all(test, feature = "headless"))]
+mod test {
+    use std::{sync::mpsc, time::Duration};
+
+    use super::*;
+    use crate::BackendType;
+
+    /// Minimal channel receiver future that's woken by the sender thread.
+    struct Recv {
+        rx: mpsc::Receiver<u32>,
+        waker: Arc<Mutex<Option<Waker>>>,
     }
-    unsafe fn drop(_data: *const ()) {
-        // Nothing to do
+
+    impl Future for Recv {
+        type Output = u32;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<u32> {
+            *self.waker.lock().unwrap() = Some(cx.waker().clone());
+            match self.rx.try_recv() {
+                Ok(x) => Poll::Ready(x),
+                Err(_) => Poll::Pending,
+            }
+        }
+    }
+
+    #[test]
+    fn external_wake() {
+        crate::select_backend(BackendType::Headless);
+        crate::run("test", async {
+            let (tx, rx) = mpsc::channel();
+            let waker: Arc<Mutex<Option<Waker>>> = Default::default();
+
+            std::thread::spawn({
+                let waker = waker.clone();
+                move || {
+                    std::thread::sleep(Duration::from_millis(10));
+                    tx.send(123).unwrap();
+                    if let Some(waker) = waker.lock().unwrap().take() {
+                        waker.wake();
+                    }
+                }
+            });
+
+            crate::draw_chars(0, 0, &[]).await;
+            assert_eq!(Recv { rx, waker }.await, 123);
+            crate::draw_chars(0, 0, &[]).await;
+        });
+    }
+
+    #[test]
+    fn spawned_tasks() {
+        crate::select_backend(BackendType::Headless);
+        crate::run("test", async {
+            let counter = Rc::new(Cell::new(0));
+
+            // Ticks once per frame.
+            spawn({
+                let counter = counter.clone();
+                async move {
+                    loop {
+                        counter.set(counter.get() + 1);
+                        crate::draw_chars(0, 0, &[]).await;
+                    }
+                }
+            });
+
+            let handle = spawn(async {
+                for _ in 0..3 {
+                    FrameFuture::default().await;
+                }
+                "done"
+            });
+
+            assert!(!handle.is_finished());
+            assert_eq!(handle.await, "done");
+            assert_eq!(counter.get(), 4);
+        });
     }
-    const VTABLE: RawWakerVTable =
-        RawWakerVTable::new(clone, wake, wake_by_ref, drop);
-    let raw_waker = RawWaker::new(std::ptr::null(), &VTABLE);
-    unsafe { Waker::from_raw(raw_waker) }
 }
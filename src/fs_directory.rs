@@ -45,6 +45,14 @@ impl Directory {
         Ok(Directory(path))
     }
 
+    /// Open a directory at an arbitrary path, creating it if needed.
+    #[cfg(test)]
+    pub(crate) fn at(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        fs::create_dir_all(&path)?;
+        Ok(Directory(path))
+    }
+
     pub fn list(&self) -> Vec<String> {
         let Ok(entries) = fs::read_dir(&self.0) else {
             return Default::default();
@@ -1,91 +1,156 @@
 //! Graphical desktop application backend.
-use std::{future::Future, sync::Mutex};
+use std::sync::Mutex;
 
-use crate::{FontSheet, FrameFuture, KeyTyped, prelude::*};
+use crate::{
+    Backend, BackgroundPolicy, Executor, FontSheet, KeyTyped, RunConfig,
+    TimedEvent, prelude::*,
+};
 
 use self::runtime::Handle;
 
 mod event;
 mod runtime;
 
-pub fn run(window_title: &str, amain: impl Future<Output = ()> + 'static) {
-    let config = miniquad::conf::Conf {
-        window_title: window_title.to_owned(),
-        window_width: 1280,
-        window_height: 720,
-        ..Default::default()
-    };
+/// Graphical desktop backend, selected with `BackendType::Gui`.
+pub(crate) struct Gui;
+
+impl Backend for Gui {
+    fn run(&self, config: &RunConfig, executor: Executor) {
+        let window = &config.window;
+        let conf = miniquad::conf::Conf {
+            window_title: window.title.clone(),
+            window_width: window.size.0 as i32,
+            window_height: window.size.1 as i32,
+            fullscreen: window.fullscreen,
+            window_resizable: config.gui.resizable,
+            high_dpi: config.gui.high_dpi,
+            ..Default::default()
+        };
+
+        let show_cursor = window.show_cursor;
+        miniquad::start(conf, move || {
+            runtime::RUNTIME
+                .set(Mutex::new(runtime::Runtime::new()))
+                .map_err(|_| panic!("backend initialized twice"))
+                .unwrap();
+
+            if !show_cursor {
+                miniquad::window::show_mouse(false);
+            }
+
+            Box::new(Handle { executor })
+        });
+    }
 
-    unsafe {
-        runtime::FUTURE = Some(Box::pin(amain));
+    fn set_fullscreen(&self, fullscreen: bool) {
+        miniquad::window::set_fullscreen(fullscreen);
     }
 
-    miniquad::start(config, move || {
-        runtime::RUNTIME
-            .set(Mutex::new(runtime::Runtime::new()))
-            .map_err(|_| panic!("backend initialized twice"))
-            .unwrap();
+    fn set_window_size(&self, w: u32, h: u32) {
+        miniquad::window::set_window_size(w, h);
+    }
 
-        Box::new(Handle)
-    });
-}
+    fn set_window_title(&self, _title: &str) {
+        // Miniquad can only set the title when opening the window.
+        log::warn!("set_window_title is not supported on the GUI backend");
+    }
 
-pub fn set_font(sheet: &FontSheet) {
-    runtime::with(|r| r.set_font(sheet));
-}
+    fn show_cursor(&self, show: bool) {
+        miniquad::window::show_mouse(show);
+    }
 
-pub fn set_palette(palette: &[Rgba; 16]) {
-    runtime::with(|r| r.set_palette(palette));
-}
+    fn set_mouse_capture(&self, capture: bool) {
+        runtime::with(|r| {
+            r.mouse_capture = capture;
+            if !capture {
+                r.mouse_state.release_all();
+            }
+        });
+    }
 
-pub fn draw_pixels(w: u32, h: u32, buffer: &[crate::Rgba]) -> FrameFuture {
-    runtime::with(|r| r.draw_pixels(w, h, buffer));
-    FrameFuture::default()
-}
+    fn set_font(&self, sheet: &FontSheet) {
+        runtime::with(|r| r.set_font(sheet));
+    }
 
-pub fn draw_chars(w: u32, h: u32, buffer: &[crate::CharCell]) -> FrameFuture {
-    runtime::with(|r| r.draw_chars(w, h, buffer));
-    FrameFuture::default()
-}
+    fn set_palette(&self, palette: &[Rgba; 16]) {
+        runtime::with(|r| r.set_palette(palette));
+    }
 
-pub fn pixel_resolution() -> (u32, u32) {
-    runtime::with(|r| r.pixel_resolution())
-}
+    fn draw_pixels(&self, w: u32, h: u32, buffer: &[Rgba]) {
+        runtime::with(|r| r.draw_pixels(w, h, buffer));
+    }
 
-pub fn char_resolution(max_w: u32, max_h: u32) -> (u32, u32) {
-    runtime::with(|r| r.char_resolution(max_w, max_h))
-}
+    fn draw_chars(&self, w: u32, h: u32, buffer: &[CharCell]) {
+        runtime::with(|r| r.draw_chars(w, h, buffer));
+    }
 
-pub fn now() -> f64 {
-    miniquad::date::now()
-}
+    fn pixel_resolution(&self) -> (u32, u32) {
+        runtime::with(|r| r.pixel_resolution())
+    }
 
-pub fn sleep(seconds: f64) {
-    // WASM Does not have a sleep function, so busy-loop instead.
-    #[cfg(target_arch = "wasm32")]
-    {
-        let start = now();
-        while now() - start < seconds {}
+    fn char_resolution(&self, max_w: u32, max_h: u32) -> (u32, u32) {
+        runtime::with(|r| r.char_resolution(max_w, max_h))
     }
 
-    #[cfg(not(target_arch = "wasm32"))]
-    {
-        std::thread::sleep(std::time::Duration::from_secs_f64(seconds));
+    fn now(&self) -> f64 {
+        miniquad::date::now()
     }
-}
 
-pub fn is_down(key: Key) -> bool {
-    runtime::with(|r| r.key_down.contains(&key))
-}
+    fn sleep(&self, seconds: f64) {
+        // WASM Does not have a sleep function, so busy-loop instead.
+        #[cfg(target_arch = "wasm32")]
+        {
+            let start = self.now();
+            while self.now() - start < seconds {}
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            std::thread::sleep(std::time::Duration::from_secs_f64(seconds));
+        }
+    }
 
-pub fn keypress() -> KeyTyped {
-    runtime::with(|r| r.keypress.front().copied().unwrap_or_default())
-}
+    fn is_down(&self, key: Key) -> bool {
+        runtime::with(|r| r.key_down.contains(&key))
+    }
 
-pub fn mouse_state() -> MouseState {
-    runtime::with(|r| r.mouse_state)
-}
+    fn keypress(&self) -> KeyTyped {
+        runtime::with(|r| r.keypress.front().copied().unwrap_or_default())
+    }
+
+    fn mouse_state(&self) -> MouseState {
+        runtime::with(|r| r.mouse_state)
+    }
 
-pub fn backend_type() -> BackendType {
-    BackendType::Gui
+    fn events(&self) -> Vec<TimedEvent> {
+        runtime::with(|r| r.events.clone())
+    }
+
+    fn is_focused(&self) -> bool {
+        !self.is_minimized()
+    }
+
+    fn is_minimized(&self) -> bool {
+        runtime::with(|r| r.minimized)
+    }
+
+    fn set_background_policy(&self, policy: BackgroundPolicy) {
+        runtime::with(|r| r.background_policy = policy);
+    }
+
+    fn set_click_interval(&self, seconds: f64) {
+        runtime::with(|r| r.click_interval = seconds);
+    }
+
+    fn clipboard_get(&self) -> Option<String> {
+        miniquad::window::clipboard_get()
+    }
+
+    fn clipboard_set(&self, text: &str) {
+        miniquad::window::clipboard_set(text);
+    }
+
+    fn backend_type(&self) -> BackendType {
+        BackendType::Gui
+    }
 }
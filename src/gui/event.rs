@@ -133,11 +133,11 @@ impl TryFrom<miniquad::KeyCode> for crate::Key {
             KeyCode::PageDown => Ok(PageDown),
             KeyCode::Home => Ok(Home),
             KeyCode::End => Ok(End),
-            KeyCode::CapsLock => Err(()),
-            KeyCode::ScrollLock => Err(()),
-            KeyCode::NumLock => Err(()),
-            KeyCode::PrintScreen => Err(()),
-            KeyCode::Pause => Err(()),
+            KeyCode::CapsLock => Ok(CapsLock),
+            KeyCode::ScrollLock => Ok(ScrollLock),
+            KeyCode::NumLock => Ok(NumLock),
+            KeyCode::PrintScreen => Ok(PrintScreen),
+            KeyCode::Pause => Ok(Pause),
             KeyCode::F1 => Ok(F(1)),
             KeyCode::F2 => Ok(F(2)),
             KeyCode::F3 => Ok(F(3)),
@@ -163,23 +163,23 @@ impl TryFrom<miniquad::KeyCode> for crate::Key {
             KeyCode::F23 => Ok(F(23)),
             KeyCode::F24 => Ok(F(24)),
             KeyCode::F25 => Ok(F(25)),
-            KeyCode::Kp0 => Ok(Char('0')),
-            KeyCode::Kp1 => Ok(Char('1')),
-            KeyCode::Kp2 => Ok(Char('2')),
-            KeyCode::Kp3 => Ok(Char('3')),
-            KeyCode::Kp4 => Ok(Char('4')),
-            KeyCode::Kp5 => Ok(Char('5')),
-            KeyCode::Kp6 => Ok(Char('6')),
-            KeyCode::Kp7 => Ok(Char('7')),
-            KeyCode::Kp8 => Ok(Char('8')),
-            KeyCode::Kp9 => Ok(Char('9')),
-            KeyCode::KpDecimal => Ok(Char('.')),
-            KeyCode::KpDivide => Ok(Char('/')),
-            KeyCode::KpMultiply => Ok(Char('*')),
-            KeyCode::KpSubtract => Ok(Char('-')),
-            KeyCode::KpAdd => Ok(Char('+')),
+            KeyCode::Kp0 => Ok(Keypad('0')),
+            KeyCode::Kp1 => Ok(Keypad('1')),
+            KeyCode::Kp2 => Ok(Keypad('2')),
+            KeyCode::Kp3 => Ok(Keypad('3')),
+            KeyCode::Kp4 => Ok(Keypad('4')),
+            KeyCode::Kp5 => Ok(Keypad('5')),
+            KeyCode::Kp6 => Ok(Keypad('6')),
+            KeyCode::Kp7 => Ok(Keypad('7')),
+            KeyCode::Kp8 => Ok(Keypad('8')),
+            KeyCode::Kp9 => Ok(Keypad('9')),
+            KeyCode::KpDecimal => Ok(Keypad('.')),
+            KeyCode::KpDivide => Ok(Keypad('/')),
+            KeyCode::KpMultiply => Ok(Keypad('*')),
+            KeyCode::KpSubtract => Ok(Keypad('-')),
+            KeyCode::KpAdd => Ok(Keypad('+')),
             KeyCode::KpEnter => Ok(Enter),
-            KeyCode::KpEqual => Ok(Char('=')),
+            KeyCode::KpEqual => Ok(Keypad('=')),
             KeyCode::LeftShift => Ok(Shift),
             KeyCode::LeftControl => Ok(Ctrl),
             KeyCode::LeftAlt => Ok(Alt),
@@ -188,7 +188,7 @@ impl TryFrom<miniquad::KeyCode> for crate::Key {
             KeyCode::RightControl => Ok(Ctrl),
             KeyCode::RightAlt => Ok(Alt),
             KeyCode::RightSuper => Ok(Icon),
-            KeyCode::Menu => Err(()),
+            KeyCode::Menu => Ok(Menu),
             KeyCode::Back => Err(()),
             KeyCode::Unknown => Err(()),
         }
@@ -1,19 +1,18 @@
 use std::{
     collections::VecDeque,
-    future::Future,
-    pin::Pin,
     sync::{Mutex, OnceLock},
 };
 
 use miniquad::*;
 use rustc_hash::FxHashSet as HashSet;
 
-use crate::{FontSheet, Key, KeyTyped, MouseState, Rgba, X256Color};
+use crate::{
+    BackgroundPolicy, Event, Executor, FontSheet, Key, KeyTyped, MouseState,
+    Rgba, TimedEvent, X256Color,
+};
 
 pub static RUNTIME: OnceLock<Mutex<Runtime>> = OnceLock::new();
 
-pub static mut FUTURE: Option<Pin<Box<dyn Future<Output = ()>>>> = None;
-
 const BINDINGS_PIXEL_BUFFER_INDEX: usize = 0;
 const BINDINGS_FONT_SHEET_INDEX: usize = 1;
 const BINDINGS_TEXT_BUFFER_INDEX: usize = 2;
@@ -32,8 +31,11 @@ where
     f(&mut gui)
 }
 
-/// Dummy type to pass to miniquad, just accesses the Runtime singleton.
-pub struct Handle;
+/// Event handler to pass to miniquad, runs the application executor and
+/// otherwise just accesses the Runtime singleton.
+pub struct Handle {
+    pub executor: Executor,
+}
 
 impl EventHandler for Handle {
     fn update(&mut self) {
@@ -41,20 +43,35 @@ impl EventHandler for Handle {
     }
 
     fn draw(&mut self) {
+        if !with(|r| r.is_running()) {
+            return;
+        }
+
         // Poll on the application future, this moves application logic
         // forward to the point where it awaits for frame change.
         //
         // If the future completes, the application run has ended and
         // we should quit.
-        if unsafe { crate::exec::poll(FUTURE.as_mut().unwrap()) }.is_some() {
+        if self.executor.poll().is_ready() {
             window::quit();
             return;
         }
 
         // Update input stack machines.
         with(|r| {
+            r.end_frame();
+
             r.keypress.pop_front();
             r.mouse_state.frame_update();
+            r.events.clear();
+            crate::testing::apply(
+                date::now(),
+                r.click_interval,
+                &mut r.keypress,
+                &mut r.key_down,
+                &mut r.mouse_state,
+                &mut r.events,
+            );
         });
     }
 
@@ -71,15 +88,26 @@ impl EventHandler for Handle {
                 if !matches!(typed.key(), Key::Char(_)) {
                     r.keypress.push_back(typed);
                 }
+                // Keypad keys get a char event too, but they have already
+                // been reported here.
+                r.keypad_char = matches!(typed.key(), Key::Keypad(_));
 
                 r.key_down.insert(typed.key().char_to_lowercase());
+                r.push_event(if repeat {
+                    Event::KeyRepeat(typed)
+                } else {
+                    Event::KeyDown(typed)
+                });
             }
         })
     }
 
     fn key_up_event(&mut self, keycode: KeyCode, keymods: KeyMods) {
         if let Ok(typed) = KeyTyped::try_from((keycode, keymods, false)) {
-            with(|r| r.key_down.remove(&typed.key().char_to_lowercase()));
+            with(|r| {
+                r.key_down.remove(&typed.key().char_to_lowercase());
+                r.push_event(Event::KeyUp(typed));
+            });
         }
     }
 
@@ -114,17 +142,61 @@ impl EventHandler for Handle {
         // Shift must be false with printable keys.
         mods.shift = false;
         let typed = KeyTyped::new(Key::Char(character), mods, repeat);
-        with(|r| r.keypress.push_back(typed));
+        with(|r| {
+            if !std::mem::take(&mut r.keypad_char) {
+                r.keypress.push_back(typed);
+            }
+            if let Some(c) = typed.text() {
+                r.push_event(Event::Text(c));
+            }
+        });
+    }
+
+    fn resize_event(&mut self, _width: f32, _height: f32) {
+        with(|r| r.push_event(Event::Resize));
+    }
+
+    // Miniquad doesn't report focus changes, treat minimizing as losing
+    // focus.
+
+    fn window_minimized_event(&mut self) {
+        with(|r| {
+            r.minimized = true;
+            r.push_event(Event::Minimize(true));
+            r.push_event(Event::Focus(false));
+        });
+    }
+
+    fn window_restored_event(&mut self) {
+        with(|r| {
+            r.minimized = false;
+            r.push_event(Event::Minimize(false));
+            r.push_event(Event::Focus(true));
+        });
     }
 
     fn mouse_motion_event(&mut self, x: f32, y: f32) {
-        with(|r| *r.mouse_state.cursor_pos_mut() = r.transform_mouse_pos(x, y));
+        with(|r| {
+            if !r.mouse_capture {
+                return;
+            }
+            let pos = r.transform_mouse_pos(x, y);
+            *r.mouse_state.cursor_pos_mut() = pos;
+            r.push_event(Event::MouseMove(pos));
+        });
     }
 
     fn mouse_wheel_event(&mut self, x: f32, y: f32) {
         let (u, v) = ((-x as i32).signum(), (-y as i32).signum());
         if u != 0 || v != 0 {
-            with(|r| r.mouse_state.scroll(u, v));
+            with(|r| {
+                if !r.mouse_capture {
+                    return;
+                }
+                r.mouse_state.scroll(u, v);
+                let pos = r.mouse_state.cursor_pos();
+                r.push_event(Event::Scroll(pos, [u, v]));
+            });
         }
     }
 
@@ -135,7 +207,20 @@ impl EventHandler for Handle {
         _y: f32,
     ) {
         if let Ok(button) = button.try_into() {
-            with(|r| r.mouse_state.button_down(button));
+            with(|r| {
+                if !r.mouse_capture {
+                    return;
+                }
+                let mods = crate::KeyMods::from_held(&r.key_down);
+                r.mouse_state.button_down(
+                    button,
+                    mods,
+                    date::now(),
+                    r.click_interval,
+                );
+                let pos = r.mouse_state.cursor_pos();
+                r.push_event(Event::MouseDown(pos, button));
+            });
         }
     }
 
@@ -146,7 +231,14 @@ impl EventHandler for Handle {
         _y: f32,
     ) {
         if let Ok(button) = button.try_into() {
-            with(|r| r.mouse_state.button_up(button));
+            with(|r| {
+                if !r.mouse_capture {
+                    return;
+                }
+                r.mouse_state.button_up(button);
+                let pos = r.mouse_state.cursor_pos();
+                r.push_event(Event::MouseUp(pos, button));
+            });
         }
     }
 }
@@ -159,9 +251,21 @@ struct Font {
     font_size: (u32, u32),
 }
 
+/// Which kind of buffer was drawn.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Canvas {
+    Pixels,
+    Chars,
+}
+
 pub struct Runtime {
     gl: GlContext,
 
+    // The most recently drawn buffer, its data stays in the textures.
+    last_frame: Option<(Canvas, u32, u32)>,
+    // Whether the app drew something during the current frame.
+    drew: bool,
+
     pixels_pipeline: Pipeline,
     chars_pipeline: Pipeline,
     bindings: Bindings,
@@ -172,6 +276,16 @@ pub struct Runtime {
     pub(crate) key_down: HashSet<Key>,
     pub(crate) mouse_state: MouseState,
     pub(crate) keypress: VecDeque<KeyTyped>,
+    pub(crate) events: Vec<TimedEvent>,
+    // The next char event comes from a keypad key.
+    keypad_char: bool,
+    pub(crate) minimized: bool,
+    // Mouse input is ignored when false.
+    pub(crate) mouse_capture: bool,
+    pub(crate) click_interval: f64,
+    pub(crate) background_policy: BackgroundPolicy,
+    // Time of the last application frame.
+    last_frame_time: f64,
 
     mouse_offset: (i32, i32),
     mouse_scale: (i32, i32),
@@ -298,6 +412,8 @@ impl Runtime {
 
         Runtime {
             gl,
+            last_frame: None,
+            drew: false,
             pixels_pipeline,
             chars_pipeline,
             bindings,
@@ -306,6 +422,13 @@ impl Runtime {
             key_down: Default::default(),
             mouse_state: Default::default(),
             keypress: Default::default(),
+            events: Default::default(),
+            keypad_char: false,
+            minimized: false,
+            mouse_capture: true,
+            click_interval: MouseState::DEFAULT_CLICK_INTERVAL,
+            background_policy: BackgroundPolicy::Run,
+            last_frame_time: 0.0,
             mouse_offset: Default::default(),
             mouse_scale: (1, 1),
         }
@@ -319,33 +442,17 @@ impl Runtime {
     }
 
     pub fn set_font(&mut self, sheet: &FontSheet) {
-        let font_size;
-
-        let (font_image, font_chars) = {
-            assert!(
-                sheet.image.width() % 16 == 0 && sheet.image.height() % 16 == 0,
-                "Font sheet dimensions aren't a multiple of 16, sheet must be a 16x16 grid"
-            );
-            font_size = (sheet.image.width() / 16, sheet.image.height() / 16);
-            (
-                create_texture(
-                    &mut self.gl,
-                    sheet.image.width(),
-                    sheet.image.height(),
-                    sheet.image.as_raw(),
-                ),
-                sheet.chars,
-            )
-        };
-
-        let mut char_lookup = vec![0xff; 0x10000];
-        for (i, &c) in font_chars.iter().enumerate() {
-            char_lookup[c as usize] = i as u8;
-        }
+        let font_size = sheet.glyph_size();
+        let font_image = create_texture(
+            &mut self.gl,
+            sheet.image.width(),
+            sheet.image.height(),
+            sheet.image.as_raw(),
+        );
 
         self.bindings.images[BINDINGS_FONT_SHEET_INDEX] = font_image;
         self.font = Some(Font {
-            char_lookup,
+            char_lookup: sheet.char_lookup(),
             font_size,
         });
     }
@@ -368,20 +475,7 @@ impl Runtime {
             Some(bytes(buffer)),
         );
 
-        self.gl.begin_default_pass(Default::default());
-        self.gl.apply_pipeline(&self.pixels_pipeline);
-        self.gl.apply_bindings(&self.bindings);
-
-        self.pixel_canvas_scale(w, h);
-
-        self.gl.apply_uniforms(UniformsSource::table(&Uniforms {
-            terminal_size: (0.0, 0.0),
-        }));
-
-        self.clear();
-        self.gl.draw(0, 6, 1);
-        self.gl.end_render_pass();
-        self.gl.commit_frame();
+        self.render(Canvas::Pixels, w, h);
     }
 
     pub fn draw_chars(&mut self, w: u32, h: u32, buffer: &[crate::CharCell]) {
@@ -433,20 +527,50 @@ impl Runtime {
             Some(bytes(&back)),
         );
 
-        self.gl.begin_default_pass(Default::default());
-        self.gl.apply_pipeline(&self.chars_pipeline);
-        self.gl.apply_bindings(&self.bindings);
+        self.render(Canvas::Chars, w, h);
+    }
 
-        self.char_canvas_scale(w, h);
+    /// Draw a buffer whose data has been uploaded into the textures.
+    fn render(&mut self, canvas: Canvas, w: u32, h: u32) {
+        self.gl.begin_default_pass(Default::default());
 
-        self.gl.apply_uniforms(UniformsSource::table(&Uniforms {
-            terminal_size: (w as f32, h as f32),
-        }));
+        match canvas {
+            Canvas::Pixels => {
+                self.gl.apply_pipeline(&self.pixels_pipeline);
+                self.gl.apply_bindings(&self.bindings);
+                self.pixel_canvas_scale(w, h);
+                self.gl.apply_uniforms(UniformsSource::table(&Uniforms {
+                    terminal_size: (0.0, 0.0),
+                }));
+            }
+            Canvas::Chars => {
+                self.gl.apply_pipeline(&self.chars_pipeline);
+                self.gl.apply_bindings(&self.bindings);
+                self.char_canvas_scale(w, h);
+                self.gl.apply_uniforms(UniformsSource::table(&Uniforms {
+                    terminal_size: (w as f32, h as f32),
+                }));
+            }
+        }
 
         self.clear();
         self.gl.draw(0, 6, 1);
         self.gl.end_render_pass();
         self.gl.commit_frame();
+
+        self.last_frame = Some((canvas, w, h));
+        self.drew = true;
+    }
+
+    /// Redraw the previous frame if the app didn't draw anything, so the
+    /// window keeps its contents while the app is waiting for something.
+    fn end_frame(&mut self) {
+        if !self.drew
+            && let Some((canvas, w, h)) = self.last_frame
+        {
+            self.render(canvas, w, h);
+        }
+        self.drew = false;
     }
 
     pub fn pixel_resolution(&self) -> (u32, u32) {
@@ -542,6 +666,24 @@ impl Runtime {
         ret
     }
 
+    /// Return whether the application should run a frame now, and mark the
+    /// frame as run if so.
+    fn is_running(&mut self) -> bool {
+        let now = date::now();
+        if self.minimized {
+            match self.background_policy.frame_interval() {
+                Some(interval) if now - self.last_frame_time >= interval => {}
+                _ => return false,
+            }
+        }
+        self.last_frame_time = now;
+        true
+    }
+
+    fn push_event(&mut self, event: Event) {
+        self.events.push(TimedEvent::new(date::now(), event));
+    }
+
     fn transform_mouse_pos(&self, x: f32, y: f32) -> [i32; 2] {
         [
             (x as i32 - self.mouse_offset.0) / self.mouse_scale.0,
@@ -550,11 +692,7 @@ impl Runtime {
     }
 
     fn convert_color(&self, c: X256Color) -> Rgba {
-        if c.0 < 16 {
-            self.system_colors[c.0 as usize]
-        } else {
-            c.into()
-        }
+        c.resolve(&self.system_colors)
     }
 }
 
@@ -564,7 +702,7 @@ fn create_texture<T>(
     h: u32,
     data: &[T],
 ) -> TextureId {
-    let ret = gl.new_texture_from_data_and_format(
+    gl.new_texture_from_data_and_format(
         bytes(data),
         TextureParams {
             format: TextureFormat::RGBA8,
@@ -574,8 +712,7 @@ fn create_texture<T>(
             height: h as _,
             ..Default::default()
         },
-    );
-    ret
+    )
 }
 
 fn bytes<T>(v: &[T]) -> &[u8] {
@@ -0,0 +1,419 @@
+//! Headless in-memory backend for automated testing.
+//!
+//! The headless backend does not open a window or touch the terminal. It
+//! keeps the most recently drawn buffers in memory so they can be inspected
+//! with the functions in this module, and it runs on a virtual clock that
+//! advances by a fixed step every frame so test runs are deterministic.
+//!
+//! Backend state is thread-local, so every test thread gets its own
+//! independent runtime and several headless apps can run in parallel under
+//! `cargo test`.
+use std::{cell::RefCell, collections::VecDeque};
+
+use rustc_hash::FxHashSet as HashSet;
+
+use crate::{
+    Backend, Executor, KeyTyped, RunConfig, TimedEvent, WindowSettings,
+    prelude::*,
+};
+
+thread_local! {
+    static RUNTIME: RefCell<Runtime> = RefCell::new(Runtime::default());
+}
+
+fn with<F, T>(f: F) -> T
+where
+    F: FnOnce(&mut Runtime) -> T,
+{
+    RUNTIME.with(|r| f(&mut r.borrow_mut()))
+}
+
+struct Runtime {
+    char_resolution: (u32, u32),
+    pixel_resolution: (u32, u32),
+    frame_duration: f64,
+
+    time: f64,
+    frame_count: u64,
+    palette: [Rgba; 16],
+    chars: Option<(u32, u32, Vec<CharCell>)>,
+    pixels: Option<(u32, u32, Vec<Rgba>)>,
+
+    key_down: HashSet<Key>,
+    mouse_state: MouseState,
+    keypress: VecDeque<KeyTyped>,
+    events: Vec<TimedEvent>,
+    clipboard: Option<String>,
+    focused: bool,
+    minimized: bool,
+    window: WindowSettings,
+    mouse_capture: bool,
+    click_interval: f64,
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Runtime {
+            char_resolution: (80, 24),
+            pixel_resolution: (640, 360),
+            frame_duration: 1.0 / 60.0,
+            time: 0.0,
+            frame_count: 0,
+            palette: std::array::from_fn(|i| X256Color(i as u8).into()),
+            chars: None,
+            pixels: None,
+            key_down: Default::default(),
+            mouse_state: Default::default(),
+            keypress: Default::default(),
+            events: Default::default(),
+            clipboard: None,
+            focused: true,
+            minimized: false,
+            window: Default::default(),
+            mouse_capture: true,
+            click_interval: MouseState::DEFAULT_CLICK_INTERVAL,
+        }
+    }
+}
+
+/// Headless backend, selected with `BackendType::Headless`.
+pub(crate) struct Headless;
+
+impl Backend for Headless {
+    fn run(&self, config: &RunConfig, mut executor: Executor) {
+        with(|r| r.window = config.window.clone());
+        while executor.poll().is_pending() {
+            with(|r| {
+                r.keypress.pop_front();
+                r.mouse_state.frame_update();
+                r.events.clear();
+                r.time += r.frame_duration;
+                r.frame_count += 1;
+                let mouse_state = r.mouse_state;
+                crate::testing::apply(
+                    r.time,
+                    r.click_interval,
+                    &mut r.keypress,
+                    &mut r.key_down,
+                    &mut r.mouse_state,
+                    &mut r.events,
+                );
+                if !r.mouse_capture {
+                    // Discard synthetic mouse input.
+                    r.mouse_state = mouse_state;
+                    r.events.retain(|e| !e.event.is_mouse());
+                }
+            });
+        }
+    }
+
+    fn set_fullscreen(&self, fullscreen: bool) {
+        with(|r| r.window.fullscreen = fullscreen);
+    }
+
+    fn set_window_size(&self, w: u32, h: u32) {
+        with(|r| r.window.size = (w, h));
+    }
+
+    fn set_window_title(&self, title: &str) {
+        with(|r| r.window.title = title.to_owned());
+    }
+
+    fn show_cursor(&self, show: bool) {
+        with(|r| r.window.show_cursor = show);
+    }
+
+    fn set_mouse_capture(&self, capture: bool) {
+        with(|r| {
+            r.mouse_capture = capture;
+            if !capture {
+                r.mouse_state.release_all();
+            }
+        });
+    }
+
+    fn set_click_interval(&self, seconds: f64) {
+        with(|r| r.click_interval = seconds);
+    }
+
+    fn set_palette(&self, palette: &[Rgba; 16]) {
+        with(|r| r.palette = *palette);
+    }
+
+    fn draw_pixels(&self, w: u32, h: u32, buffer: &[Rgba]) {
+        assert!(buffer.len() == (w * h) as usize);
+        with(|r| r.pixels = Some((w, h, buffer.to_vec())));
+    }
+
+    fn draw_chars(&self, w: u32, h: u32, buffer: &[CharCell]) {
+        assert!(buffer.len() == (w * h) as usize);
+        with(|r| r.chars = Some((w, h, buffer.to_vec())));
+    }
+
+    fn pixel_resolution(&self) -> (u32, u32) {
+        with(|r| r.pixel_resolution)
+    }
+
+    fn char_resolution(&self, max_w: u32, max_h: u32) -> (u32, u32) {
+        let (w, h) = with(|r| r.char_resolution);
+
+        // Zoom up like the GUI backend, by the smallest integer factor
+        // that fits within the nonzero bounds.
+        let mut n = 1;
+        while (max_w > 0 && w / n > max_w) || (max_h > 0 && h / n > max_h) {
+            n += 1;
+        }
+        (w / n, h / n)
+    }
+
+    fn now(&self) -> f64 {
+        with(|r| r.time)
+    }
+
+    fn sleep(&self, seconds: f64) {
+        // Don't actually block, just move the virtual clock forward.
+        with(|r| r.time += seconds.max(0.0));
+    }
+
+    fn is_down(&self, key: Key) -> bool {
+        with(|r| r.key_down.contains(&key))
+    }
+
+    fn keypress(&self) -> KeyTyped {
+        with(|r| r.keypress.front().copied().unwrap_or_default())
+    }
+
+    fn mouse_state(&self) -> MouseState {
+        with(|r| r.mouse_state)
+    }
+
+    fn events(&self) -> Vec<TimedEvent> {
+        with(|r| r.events.clone())
+    }
+
+    fn is_focused(&self) -> bool {
+        with(|r| r.focused)
+    }
+
+    fn is_minimized(&self) -> bool {
+        with(|r| r.minimized)
+    }
+
+    fn clipboard_get(&self) -> Option<String> {
+        with(|r| r.clipboard.clone())
+    }
+
+    fn clipboard_set(&self, text: &str) {
+        with(|r| r.clipboard = Some(text.to_owned()));
+    }
+
+    fn backend_type(&self) -> BackendType {
+        BackendType::Headless
+    }
+}
+
+// Public control and inspection API
+
+/// Set the char cell resolution the backend will report.
+///
+/// This is the resolution at the smallest character size, when
+/// `navni::char_resolution` is given bounds, the characters are zoomed up
+/// to fit as on GUI backends. Defaults to 80x24. Changing the resolution sends an `Event::Resize`.
+pub fn set_char_resolution(w: u32, h: u32) {
+    if with(|r| std::mem::replace(&mut r.char_resolution, (w, h))) != (w, h) {
+        crate::testing::push_event(Event::Resize);
+    }
+}
+
+/// Set the pixel resolution the backend will report.
+///
+/// Defaults to 640x360. Changing the resolution sends an `Event::Resize`.
+pub fn set_pixel_resolution(w: u32, h: u32) {
+    if with(|r| std::mem::replace(&mut r.pixel_resolution, (w, h))) != (w, h) {
+        crate::testing::push_event(Event::Resize);
+    }
+}
+
+/// Set whether the window has input focus and send an `Event::Focus`.
+pub fn set_focused(focused: bool) {
+    with(|r| r.focused = focused);
+    crate::testing::push_event(Event::Focus(focused));
+}
+
+/// Set whether the window is minimized and send an `Event::Minimize`.
+pub fn set_minimized(minimized: bool) {
+    with(|r| r.minimized = minimized);
+    crate::testing::push_event(Event::Minimize(minimized));
+}
+
+/// Set how many seconds the virtual clock advances every frame.
+///
+/// Defaults to 1/60 seconds.
+pub fn set_frame_duration(seconds: f64) {
+    with(|r| r.frame_duration = seconds);
+}
+
+/// Return the number of frames that have been completed.
+pub fn frame_count() -> u64 {
+    with(|r| r.frame_count)
+}
+
+/// Return the window settings the application started with, updated by
+/// the window control calls it has made since.
+pub fn window() -> WindowSettings {
+    with(|r| r.window.clone())
+}
+
+/// Return the palette most recently set with `navni::set_palette`.
+pub fn palette() -> [Rgba; 16] {
+    with(|r| r.palette)
+}
+
+/// Return the buffer from the most recent `navni::draw_chars` call.
+pub fn chars() -> Option<(u32, u32, Vec<CharCell>)> {
+    with(|r| r.chars.clone())
+}
+
+/// Return the buffer from the most recent `navni::draw_pixels` call.
+pub fn pixels() -> Option<(u32, u32, Vec<Rgba>)> {
+    with(|r| r.pixels.clone())
+}
+
+/// Reset the backend to its initial state.
+pub fn reset() {
+    with(|r| *r = Runtime::default());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn headless_run() {
+        set_char_resolution(20, 10);
+
+        crate::select_backend(BackendType::Headless);
+        crate::run("test", async {
+            let (w, h) = crate::char_resolution(0, 0);
+            assert_eq!((w, h), (20, 10));
+
+            for i in 0..3 {
+                let buf =
+                    vec![CharCell::c((b'a' + i) as char); (w * h) as usize];
+                crate::draw_chars(w, h, &buf).await;
+            }
+        });
+
+        assert_eq!(frame_count(), 3);
+        assert!((crate::now() - 3.0 / 60.0).abs() < 1e-9);
+
+        let (w, h, buf) = chars().unwrap();
+        assert_eq!((w, h), (20, 10));
+        assert!(buf.iter().all(|c| char::from(*c) == 'c'));
+        assert!(pixels().is_none());
+    }
+
+    #[test]
+    fn char_resolution_bounds() {
+        crate::select_backend(BackendType::Headless);
+        crate::run("test", async {
+            set_char_resolution(200, 100);
+            assert_eq!(crate::char_resolution(0, 0), (200, 100));
+            assert_eq!(crate::char_resolution(300, 100), (200, 100));
+            assert_eq!(crate::char_resolution(80, 0), (66, 33));
+            assert_eq!(crate::char_resolution(0, 40), (66, 33));
+            assert_eq!(crate::char_resolution(100, 20), (40, 20));
+        });
+    }
+
+    #[test]
+    fn clipboard() {
+        crate::select_backend(BackendType::Headless);
+        crate::run("test", async {
+            assert_eq!(crate::clipboard_get(), None);
+            crate::clipboard_set("seed 1234");
+            assert_eq!(crate::clipboard_get().as_deref(), Some("seed 1234"));
+
+            crate::testing::paste("xyzzy");
+            crate::draw_chars(0, 0, &[]).await;
+            assert_eq!(
+                crate::events()[0].event,
+                Event::Paste("xyzzy".to_owned())
+            );
+        });
+    }
+
+    #[test]
+    fn window_control() {
+        crate::select_backend(BackendType::Headless);
+        crate::run(
+            WindowSettings {
+                size: (800, 600),
+                ..WindowSettings::new("game")
+            },
+            async {
+                crate::set_window_title("game - save 1");
+                crate::set_fullscreen(true);
+                crate::show_cursor(false);
+            },
+        );
+
+        assert_eq!(
+            window(),
+            WindowSettings {
+                title: "game - save 1".to_owned(),
+                size: (800, 600),
+                fullscreen: true,
+                show_cursor: false,
+            }
+        );
+    }
+
+    #[test]
+    fn mouse_capture() {
+        use crate::testing;
+
+        crate::select_backend(BackendType::Headless);
+        crate::run("test", async {
+            testing::mouse_move([1, 2]);
+            testing::mouse_down(MouseButton::Left);
+            crate::draw_chars(0, 0, &[]).await;
+            assert!(crate::mouse_state().is_down(MouseButton::Left));
+
+            crate::set_mouse_capture(false);
+            assert!(crate::mouse_state().is_hover());
+            assert_eq!(crate::mouse_state().cursor_pos(), [1, 2]);
+            testing::click([5, 5], MouseButton::Left);
+            crate::draw_chars(0, 0, &[]).await;
+            assert!(crate::mouse_state().is_hover());
+            assert_eq!(crate::mouse_state().cursor_pos(), [1, 2]);
+            assert!(crate::events().is_empty());
+
+            crate::set_mouse_capture(true);
+            testing::click([5, 5], MouseButton::Left);
+            crate::draw_chars(0, 0, &[]).await;
+            assert!(crate::mouse_state().release(MouseButton::Left).is_some());
+        });
+    }
+
+    #[test]
+    fn window_state() {
+        crate::select_backend(BackendType::Headless);
+        crate::run("test", async {
+            assert!(crate::is_focused());
+            set_focused(false);
+            set_minimized(true);
+            set_char_resolution(40, 20);
+            assert!(!crate::is_focused());
+            assert!(crate::is_minimized());
+
+            crate::draw_chars(0, 0, &[]).await;
+            let events: Vec<Event> =
+                crate::events().into_iter().map(|e| e.event).collect();
+            assert_eq!(
+                events,
+                vec![Event::Focus(false), Event::Minimize(true), Event::Resize]
+            );
+        });
+    }
+}
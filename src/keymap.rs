@@ -0,0 +1,471 @@
+//! Mapping key sequences to application actions.
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use anyhow::{Context, bail};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+
+use crate::{Directory, Key, KeyTyped};
+
+/// Sequence of one or more key chords, eg. `"C-x C-s"`.
+///
+/// Uses the `KeyTyped` string format for the chords, separated by spaces.
+#[derive(
+    Clone,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    Hash,
+    Debug,
+    SerializeDisplay,
+    DeserializeFromStr,
+)]
+pub struct KeySeq(Vec<KeyTyped>);
+
+impl KeySeq {
+    pub fn keys(&self) -> &[KeyTyped] {
+        &self.0
+    }
+
+    /// Return whether one of the sequences starts with the other.
+    fn overlaps(&self, other: &KeySeq) -> bool {
+        self.0.starts_with(&other.0) || other.0.starts_with(&self.0)
+    }
+}
+
+impl From<KeyTyped> for KeySeq {
+    fn from(key: KeyTyped) -> Self {
+        KeySeq(vec![key.ignore_repeat_flag()])
+    }
+}
+
+impl fmt::Display for KeySeq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, key) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{key}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for KeySeq {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keys = s
+            .split_whitespace()
+            .map(|a| a.parse())
+            .collect::<Result<Vec<KeyTyped>, _>>()?;
+        if keys.is_empty() {
+            bail!("Empty key sequence");
+        }
+        if keys.iter().any(|k| !k.is_some() || is_modifier(k.key())) {
+            bail!("Key sequence {s:?} has a bare modifier key");
+        }
+        Ok(KeySeq(keys))
+    }
+}
+
+/// Bindings from key sequences to actions of type `A`.
+///
+/// Feed the keypresses the application receives to `feed` and it returns
+/// the action when a bound sequence is completed:
+///
+/// ```no_run
+/// # #[derive(Clone, PartialEq)] enum Action { Save, Quit }
+/// # async fn f() {
+/// let mut keymap = navni::Keymap::new();
+/// keymap.bind("C-x C-s", Action::Save).unwrap();
+/// keymap.bind("C-x C-c", Action::Quit).unwrap();
+///
+/// loop {
+///     match keymap.feed(navni::keypress()) {
+///         Some(Action::Save) => { /* ... */ }
+///         Some(Action::Quit) => break,
+///         None => {}
+///     }
+///     navni::draw_chars(0, 0, &[]).await;
+/// }
+/// # }
+/// ```
+///
+/// A sequence can't be bound if it's a prefix of another bound sequence or
+/// the other sequence is a prefix of it, since it would be ambiguous which
+/// one the user is typing. Several sequences can be bound to the same
+/// action.
+///
+/// Keymaps serialize as a map from key sequence strings to actions. They
+/// can also be saved to and loaded from a `Directory` as text with one
+/// binding per line, the key sequence followed by the action:
+///
+/// ```text
+/// C-x C-c Quit
+/// C-x C-s Save
+/// ```
+#[derive(Clone, Debug)]
+pub struct Keymap<A> {
+    bindings: BTreeMap<KeySeq, A>,
+    // Keys of a partially typed sequence.
+    pending: Vec<KeyTyped>,
+}
+
+impl<A> Default for Keymap<A> {
+    fn default() -> Self {
+        Keymap {
+            bindings: Default::default(),
+            pending: Default::default(),
+        }
+    }
+}
+
+impl<A: Clone + PartialEq> Keymap<A> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Bind a key sequence given in the `KeySeq` string format to an action.
+    ///
+    /// Fails if the string is malformed or the sequence conflicts with an
+    /// existing binding.
+    pub fn bind(&mut self, keys: &str, action: A) -> anyhow::Result<()> {
+        self.bind_seq(keys.parse()?, action)
+    }
+
+    /// Bind a key sequence to an action.
+    ///
+    /// Fails if the sequence conflicts with an existing binding.
+    pub fn bind_seq(&mut self, keys: KeySeq, action: A) -> anyhow::Result<()> {
+        if self.bindings.get(&keys) == Some(&action) {
+            return Ok(());
+        }
+        let conflicts = self.conflicts(&keys);
+        if !conflicts.is_empty() {
+            let conflicts: Vec<String> = conflicts
+                .iter()
+                .map(|a| format!("{:?}", a.to_string()))
+                .collect();
+            bail!(
+                "{:?} conflicts with {}",
+                keys.to_string(),
+                conflicts.join(", ")
+            );
+        }
+        self.bindings.insert(keys, action);
+        Ok(())
+    }
+
+    /// Remove the binding of a key sequence and return its action.
+    pub fn unbind(&mut self, keys: &KeySeq) -> Option<A> {
+        self.bindings.remove(keys)
+    }
+
+    /// Return the bound sequences that would conflict with binding `keys`.
+    ///
+    /// An existing binding for exactly `keys` is included.
+    pub fn conflicts(&self, keys: &KeySeq) -> Vec<&KeySeq> {
+        self.bindings.keys().filter(|a| a.overlaps(keys)).collect()
+    }
+
+    /// Return the action bound to a key sequence.
+    pub fn get(&self, keys: &KeySeq) -> Option<&A> {
+        self.bindings.get(keys)
+    }
+
+    /// Return all sequences bound to an action, eg. for showing in a help
+    /// screen.
+    pub fn keys_for(&self, action: &A) -> Vec<&KeySeq> {
+        self.bindings
+            .iter()
+            .filter_map(|(k, a)| (a == action).then_some(k))
+            .collect()
+    }
+
+    /// Iterate through all bindings in key sequence order.
+    pub fn iter(&self) -> impl Iterator<Item = (&KeySeq, &A)> {
+        self.bindings.iter()
+    }
+
+    /// Process a keypress, return an action if it completes a bound
+    /// sequence.
+    ///
+    /// Modifier keys pressed on their own and empty keypresses are ignored,
+    /// so this can be called with `navni::keypress()` every frame. If a key
+    /// doesn't continue the sequence typed so far, the partial sequence is
+    /// dropped and the key is tried as the start of a new one.
+    pub fn feed(&mut self, key: KeyTyped) -> Option<A> {
+        if !key.is_some() || is_modifier(key.key()) {
+            return None;
+        }
+
+        self.pending.push(key.ignore_repeat_flag());
+        let seq = KeySeq(std::mem::take(&mut self.pending));
+
+        if let Some(action) = self.bindings.get(&seq) {
+            return Some(action.clone());
+        }
+
+        // Sequences that start with the pending keys sort right after them.
+        let is_prefix = self
+            .bindings
+            .range(&seq..)
+            .next()
+            .is_some_and(|(k, _)| k.0.starts_with(&seq.0));
+        if is_prefix {
+            self.pending = seq.0;
+            return None;
+        }
+
+        if seq.0.len() > 1 {
+            self.feed(key)
+        } else {
+            None
+        }
+    }
+
+    /// Return the keys of a partially typed sequence.
+    pub fn pending(&self) -> &[KeyTyped] {
+        &self.pending
+    }
+
+    /// Drop a partially typed sequence.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+    }
+}
+
+impl<A: Clone + PartialEq + FromStr + fmt::Display> Keymap<A> {
+    /// Load a keymap saved with `save` from a file in a directory.
+    ///
+    /// Fails if the file can't be read, or if a line is malformed or
+    /// conflicts with an earlier binding.
+    pub fn load(dir: &Directory, file_name: &str) -> anyhow::Result<Self> {
+        dir.read(file_name)?.parse()
+    }
+
+    /// Save the keymap as a text file in a directory.
+    ///
+    /// Fails if an action's string form is empty or contains whitespace,
+    /// since it couldn't be told apart from the key sequence when loading.
+    pub fn save(
+        &self,
+        dir: &mut Directory,
+        file_name: &str,
+    ) -> anyhow::Result<()> {
+        for action in self.bindings.values() {
+            let action = action.to_string();
+            if action.is_empty() || action.contains(char::is_whitespace) {
+                bail!("Action {action:?} can't be saved in a keymap file");
+            }
+        }
+        dir.write(file_name, &self.to_string())
+    }
+}
+
+impl<A: fmt::Display> fmt::Display for Keymap<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (keys, action) in &self.bindings {
+            writeln!(f, "{keys} {action}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<A: Clone + PartialEq + FromStr + fmt::Display> FromStr for Keymap<A> {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ret = Keymap::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let parse = || -> anyhow::Result<(KeySeq, A)> {
+                let Some((keys, action)) =
+                    line.rsplit_once(char::is_whitespace)
+                else {
+                    bail!("Expected a key sequence and an action");
+                };
+                let Ok(action) = action.parse() else {
+                    bail!("Bad action {action:?}");
+                };
+                Ok((keys.parse()?, action))
+            };
+            parse()
+                .and_then(|(keys, action)| ret.bind_seq(keys, action))
+                .with_context(|| format!("Keymap line {}", i + 1))?;
+        }
+        Ok(ret)
+    }
+}
+
+impl<A: Serialize> Serialize for Keymap<A> {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        self.bindings.serialize(serializer)
+    }
+}
+
+impl<'de, A: Clone + PartialEq + Deserialize<'de>> Deserialize<'de>
+    for Keymap<A>
+{
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let bindings = BTreeMap::<KeySeq, A>::deserialize(deserializer)?;
+        let mut ret = Keymap::new();
+        for (keys, action) in bindings {
+            ret.bind_seq(keys, action)
+                .map_err(serde::de::Error::custom)?;
+        }
+        Ok(ret)
+    }
+}
+
+fn is_modifier(key: Key) -> bool {
+    matches!(key, Key::Shift | Key::Ctrl | Key::Alt | Key::Icon)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+    enum Action {
+        Save,
+        Quit,
+        Up,
+    }
+
+    impl fmt::Display for Action {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            fmt::Debug::fmt(self, f)
+        }
+    }
+
+    impl FromStr for Action {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "Save" => Ok(Action::Save),
+                "Quit" => Ok(Action::Quit),
+                "Up" => Ok(Action::Up),
+                _ => bail!("Unknown action"),
+            }
+        }
+    }
+
+    fn key(s: &str) -> KeyTyped {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn sequences() {
+        let mut keymap = Keymap::new();
+        keymap.bind("C-x C-s", Action::Save).unwrap();
+        keymap.bind("C-x C-c", Action::Quit).unwrap();
+        keymap.bind("k", Action::Up).unwrap();
+        keymap.bind("Up", Action::Up).unwrap();
+
+        assert_eq!(keymap.feed(key("C-x")), None);
+        assert_eq!(keymap.pending(), &[key("C-x")]);
+        // Modifier keys don't interrupt sequences.
+        assert_eq!(keymap.feed(key("Ctrl")), None);
+        assert_eq!(keymap.feed(Default::default()), None);
+        assert_eq!(keymap.feed(key("C-s")), Some(Action::Save));
+        assert!(keymap.pending().is_empty());
+
+        // A wrong key drops the sequence and starts over.
+        assert_eq!(keymap.feed(key("C-x")), None);
+        assert_eq!(keymap.feed(key("k")), Some(Action::Up));
+        assert_eq!(keymap.feed(key("C-x")), None);
+        assert_eq!(keymap.feed(key("C-x")), None);
+        assert_eq!(keymap.feed(key("C-c")), Some(Action::Quit));
+
+        assert_eq!(keymap.keys_for(&Action::Up).len(), 2);
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut keymap = Keymap::new();
+        keymap.bind("C-x C-s", Action::Save).unwrap();
+        keymap.bind("C-x C-s", Action::Save).unwrap();
+
+        assert!(keymap.bind("C-x", Action::Quit).is_err());
+        assert!(keymap.bind("C-x C-s C-s", Action::Quit).is_err());
+        assert!(keymap.bind("C-x C-s", Action::Quit).is_err());
+        assert!(keymap.bind("Shift", Action::Quit).is_err());
+        assert!(keymap.bind("", Action::Quit).is_err());
+
+        let seq: KeySeq = "C-x".parse().unwrap();
+        assert_eq!(keymap.conflicts(&seq).len(), 1);
+        keymap.unbind(&"C-x C-s".parse().unwrap());
+        assert!(keymap.conflicts(&seq).is_empty());
+    }
+
+    #[test]
+    fn serialization() {
+        let mut keymap = Keymap::new();
+        keymap.bind("C-x C-s", Action::Save).unwrap();
+        keymap.bind("q", Action::Quit).unwrap();
+
+        let json = serde_json::to_string(&keymap).unwrap();
+        assert_eq!(json, r#"{"q":"Quit","C-x C-s":"Save"}"#);
+
+        let loaded: Keymap<Action> = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.iter().count(), 2);
+        assert_eq!(loaded.get(&"q".parse().unwrap()), Some(&Action::Quit));
+
+        assert!(
+            serde_json::from_str::<Keymap<Action>>(
+                r#"{"C-x":"Quit","C-x C-s":"Save"}"#
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn load_and_save() {
+        let mut keymap = Keymap::new();
+        keymap.bind("C-x C-s", Action::Save).unwrap();
+        keymap.bind("C-x Sp C-c", Action::Quit).unwrap();
+        keymap.bind("Up", Action::Up).unwrap();
+        keymap.bind("A-ö", Action::Up).unwrap();
+
+        let path = std::env::temp_dir()
+            .join(format!("navni-keymap-test-{}", std::process::id()));
+        let mut dir = Directory::at(&path).unwrap();
+        keymap.save(&mut dir, "keys.txt").unwrap();
+        assert_eq!(
+            dir.read("keys.txt").unwrap(),
+            "Up Up\nC-x Sp C-c Quit\nC-x C-s Save\nA-ö Up\n"
+        );
+
+        let mut loaded: Keymap<Action> =
+            Keymap::load(&dir, "keys.txt").unwrap();
+        assert_eq!(
+            loaded.iter().collect::<Vec<_>>(),
+            keymap.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(loaded.feed(key("C-x")), None);
+        assert_eq!(loaded.feed(key("C-s")), Some(Action::Save));
+
+        // Conflicting or malformed lines are errors.
+        dir.write("bad.txt", "C-x Quit\nC-x C-s Save\n").unwrap();
+        assert!(Keymap::<Action>::load(&dir, "bad.txt").is_err());
+        dir.write("bad.txt", "C-x Exit\n").unwrap();
+        assert!(Keymap::<Action>::load(&dir, "bad.txt").is_err());
+        dir.write("bad.txt", "Save\n").unwrap();
+        assert!(Keymap::<Action>::load(&dir, "bad.txt").is_err());
+
+        dir.delete("keys.txt").unwrap();
+        dir.delete("bad.txt").unwrap();
+    }
+}
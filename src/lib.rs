@@ -2,16 +2,15 @@ use std::future::Future;
 
 #[cfg(feature = "gui")]
 mod gui;
-#[cfg(feature = "gui")]
-use gui as backend;
 
 #[cfg(feature = "tty")]
 mod tty;
-#[cfg(feature = "tty")]
-use tty as backend;
 
-#[cfg(not(any(feature = "gui", feature = "tty")))]
+#[cfg(feature = "headless")]
+pub mod headless;
+
 mod backend;
+pub use backend::Backend;
 
 mod char_cell;
 pub use char_cell::CharCell;
@@ -23,7 +22,7 @@ mod config;
 pub use config::{DEFAULT_FONT_CHARS, FontSheet};
 
 mod exec;
-pub use exec::FrameFuture;
+pub use exec::{Executor, FrameFuture, JoinHandle, spawn};
 
 mod frame_counter;
 pub use frame_counter::FrameCounter;
@@ -35,25 +34,120 @@ mod directory;
 pub use directory::Directory;
 
 mod event;
-pub use event::{Key, KeyMods, KeyTyped, MouseButton, MouseState};
+pub use event::{
+    ButtonState, Click, Event, Key, KeyMods, KeyTyped, MediaKey, MouseButton,
+    MouseState, TimedEvent,
+};
+
+mod keymap;
+pub use keymap::{KeySeq, Keymap};
 
 pub mod logger;
 
 pub mod prelude;
 
+pub mod record;
+
+pub mod snapshot;
+
+pub mod testing;
+
+mod wait;
+pub use wait::{Timeout, next_click, next_key, timeout};
+
+mod run_config;
+pub use run_config::{GuiConfig, RunConfig, TtyConfig};
+
+mod window;
+pub use window::WindowSettings;
+
 // Interface wrapper
 //
-// Having this here creates a compile-time check that each backend
-// implementation contains all the functions and lets the functions be
-// documented in one place.
+// The functions are documented here and forward to the active `Backend`
+// implementation.
 
 /// Entry point for a navni application.
 ///
 /// Start running the given async main function initialized with the given
-/// configuration. The application will terminate when the async function
-/// exits.
-pub fn run(window_title: &str, amain: impl Future<Output = ()> + 'static) {
-    backend::run(window_title, amain);
+/// configuration. The configuration can be just the window title. The
+/// application will terminate when the async function exits.
+pub fn run(
+    config: impl Into<RunConfig>,
+    amain: impl Future<Output = ()> + 'static,
+) {
+    let backend = backend::builtin();
+    backend::activate(backend.clone());
+    backend.run(&config.into(), Executor::new(amain));
+}
+
+/// Entry point for a navni application using a custom backend.
+///
+/// Works like `run`, but all navni calls made by the application go to the
+/// given backend instead of a built-in one.
+pub fn run_with_backend(
+    backend: impl Backend + 'static,
+    config: impl Into<RunConfig>,
+    amain: impl Future<Output = ()> + 'static,
+) {
+    let backend = std::rc::Rc::new(backend);
+    backend::activate(backend.clone());
+    backend.run(&config.into(), Executor::new(amain));
+}
+
+/// Switch the window between fullscreen and windowed mode.
+///
+/// Has no effect on TTY backends.
+pub fn set_fullscreen(fullscreen: bool) {
+    backend::with(|b| b.set_fullscreen(fullscreen));
+}
+
+/// Resize the window to the given size in pixels.
+///
+/// Has no effect on TTY backends.
+pub fn set_window_size(w: u32, h: u32) {
+    backend::with(|b| b.set_window_size(w, h));
+}
+
+/// Change the window title.
+///
+/// TTY backends set the terminal title. The GUI backend can't change the
+/// title after the window has been opened, set it in `WindowSettings`
+/// instead.
+pub fn set_window_title(title: &str) {
+    backend::with(|b| b.set_window_title(title));
+}
+
+/// Show or hide the operating system mouse cursor over the window.
+///
+/// Has no effect on TTY backends.
+pub fn show_cursor(show: bool) {
+    backend::with(|b| b.show_cursor(show));
+}
+
+/// Turn mouse input on or off.
+///
+/// TTY backends stop capturing the mouse from the terminal when mouse
+/// input is off, so the terminal's own text selection works again. While
+/// mouse input is off, no mouse events are reported and `mouse_state`
+/// has no buttons down and stays at the last known cursor position on all
+/// backends.
+///
+/// Mouse input starts out on, except on TTY backends when it has been
+/// turned off with `RunConfig::mouse_capture`.
+pub fn set_mouse_capture(capture: bool) {
+    backend::with(|b| b.set_mouse_capture(capture));
+}
+
+/// Set the longest time in seconds between presses of a mouse button for
+/// them to count as a double or triple click. The default is 0.5 seconds.
+///
+/// Panics if `seconds` is negative or not a finite number.
+pub fn set_click_interval(seconds: f64) {
+    assert!(
+        seconds.is_finite() && seconds >= 0.0,
+        "set_click_interval: bad interval {seconds}"
+    );
+    backend::with(|b| b.set_click_interval(seconds));
 }
 
 /// Set a custom bitmap font sheet.
@@ -61,15 +155,16 @@ pub fn run(window_title: &str, amain: impl Future<Output = ()> + 'static) {
 /// Has no effect on TTY backends, they always use the font provided by the
 /// operating system terminal.
 pub fn set_font(sheet: &FontSheet) {
-    backend::set_font(sheet);
+    backend::with(|b| b.set_font(sheet));
 }
 
 /// Set the system color palette.
 ///
-/// Has no effect on TTY backends, they always use the palette provided by the
-/// operating system terminal.
+/// TTY backends redefine the terminal's colors if the terminal supports it
+/// and restore the original colors on exit. Colors 7 and 0 also become the
+/// terminal's default foreground and background colors.
 pub fn set_palette(palette: &[Rgba; 16]) {
-    backend::set_palette(palette);
+    backend::with(|b| b.set_palette(palette));
 }
 
 /// Draw a pixel buffer of a given size to the window.
@@ -80,7 +175,8 @@ pub fn set_palette(palette: &[Rgba; 16]) {
 /// This function's result must be awaited to make the backend progress to the
 /// next frame.
 pub fn draw_pixels(w: u32, h: u32, buffer: &[Rgba]) -> FrameFuture {
-    backend::draw_pixels(w, h, buffer)
+    backend::with(|b| b.draw_pixels(w, h, buffer));
+    FrameFuture::default()
 }
 
 /// Draw a character buffer of a given size to the window.
@@ -91,37 +187,50 @@ pub fn draw_pixels(w: u32, h: u32, buffer: &[Rgba]) -> FrameFuture {
 /// This function's result must be awaited to make the backend progress to the
 /// next frame.
 pub fn draw_chars(w: u32, h: u32, buffer: &[CharCell]) -> FrameFuture {
-    backend::draw_chars(w, h, buffer)
+    backend::with(|b| b.draw_chars(w, h, buffer));
+    FrameFuture::default()
 }
 
 /// Return pixel resolution of the window.
+///
+/// On TTY backends without bitmap graphics, this depends on the current
+/// `PixelMode`.
 pub fn pixel_resolution() -> (u32, u32) {
-    backend::pixel_resolution()
+    backend::with(|b| b.pixel_resolution())
+}
+
+/// Set how TTY backends fake pixels with characters.
+///
+/// Only used when the terminal doesn't support a bitmap graphics protocol.
+/// Has no effect on GUI backends.
+pub fn set_pixel_mode(mode: PixelMode) {
+    backend::with(|b| b.set_pixel_mode(mode));
 }
 
 /// Return char cell resolution of the window, depends on font size.
 ///
-/// On GUI backends, if it looks like the terminal would get more than the
-/// given maximum of characters along either dimension, the terminal will try
-/// to zoom up the characters to fit within the bounds. This is to prevent the
-/// characters from becoming tiny on high-DPI displays. TTY backends will
+/// On GUI and headless backends, if it looks like the terminal would get
+/// more than the given maximum of characters along either dimension, the
+/// terminal will try to zoom up the characters to fit within the bounds.
+/// This is to prevent the characters from becoming tiny on high-DPI
+/// displays. TTY backends will
 /// ignore `max_w` and `max_h` and always report the actual terminal
 /// dimensions, since navni cannot affect font size on a TTY.
 ///
 /// Zero values of `max_w` and `max_h` cause no adjustment to be done along
 /// that dimension.
 pub fn char_resolution(max_w: u32, max_h: u32) -> (u32, u32) {
-    backend::char_resolution(max_w, max_h)
+    backend::with(|b| b.char_resolution(max_w, max_h))
 }
 
 /// Return current time in seconds starting from an unspecified epoch.
 pub fn now() -> f64 {
-    backend::now()
+    record::now(|| backend::with(|b| b.now()))
 }
 
 /// Sleep for given number of seconds
 pub fn sleep(seconds: f64) {
-    backend::sleep(seconds)
+    backend::with(|b| b.sleep(seconds))
 }
 
 /// Return if given key is currently held down.
@@ -130,7 +239,7 @@ pub fn sleep(seconds: f64) {
 /// what shift status is. This method is not supported on TTY backends and
 /// always returns false there.
 pub fn is_down(key: Key) -> bool {
-    backend::is_down(key)
+    record::is_down(key, || backend::with(|b| b.is_down(key)))
 }
 
 /// Return keypress from last frame.
@@ -138,20 +247,151 @@ pub fn is_down(key: Key) -> bool {
 /// Only one keypress per frame is supported, should be fast enough for
 /// any reasonable framerate.
 pub fn keypress() -> KeyTyped {
-    backend::keypress()
+    record::keypress(|| backend::with(|b| b.keypress()))
 }
 
 /// Return mouse action state from last frame.
 pub fn mouse_state() -> MouseState {
-    backend::mouse_state()
+    record::mouse_state(|| backend::with(|b| b.mouse_state()))
+}
+
+/// Return every input event that arrived during the last frame, in order.
+///
+/// Unlike `keypress`, which hands out keypresses one frame at a time, this
+/// returns all of the frame's events at once. The same events are returned
+/// until the next frame starts.
+pub fn events() -> Vec<TimedEvent> {
+    record::events(|| backend::with(|b| b.events()))
+}
+
+/// Return whether the application window has input focus.
+///
+/// GUI backends only lose focus when the window is minimized. TTY backends
+/// need a terminal that reports focus changes, otherwise they always count
+/// as focused.
+pub fn is_focused() -> bool {
+    backend::with(|b| b.is_focused())
+}
+
+/// Return whether the application window is minimized.
+///
+/// Always false on TTY backends.
+pub fn is_minimized() -> bool {
+    backend::with(|b| b.is_minimized())
+}
+
+/// Set what the application does while it's in the background.
+///
+/// GUI backends are in the background when the window is minimized, TTY
+/// backends when the terminal has lost focus. The headless backend always
+/// keeps running.
+///
+/// GUI backends start out with `BackgroundPolicy::Run` and TTY backends
+/// with `BackgroundPolicy::Block`.
+///
+/// Panics if a `Throttle` rate isn't a positive number.
+pub fn set_background_policy(policy: BackgroundPolicy) {
+    if let BackgroundPolicy::Throttle(fps) = policy {
+        assert!(fps > 0.0, "set_background_policy: bad throttle rate {fps}");
+    }
+    backend::with(|b| b.set_background_policy(policy));
+}
+
+/// Return the text on the system clipboard.
+///
+/// TTY backends can't read the terminal's clipboard, they return the text
+/// most recently pasted into the terminal or set with `clipboard_set`.
+/// Pasted text also shows up as an `Event::Paste`.
+pub fn clipboard_get() -> Option<String> {
+    backend::with(|b| b.clipboard_get())
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+/// Put text on the system clipboard.
+///
+/// TTY backends use the OSC 52 escape sequence, which not all terminals
+/// support.
+pub fn clipboard_set(text: &str) {
+    backend::with(|b| b.clipboard_set(text));
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 pub enum BackendType {
     Tty,
     Gui,
+    Headless,
+}
+
+/// How the application runs while it's in the background.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum BackgroundPolicy {
+    /// Stop running frames until the application is back in the
+    /// foreground or, on TTY backends, there is new input.
+    Block,
+    /// Keep running frames as usual.
+    Run,
+    /// Keep running at most the given number of frames per second.
+    Throttle(f64),
+}
+
+impl BackgroundPolicy {
+    /// Return how long to wait between frames in the background, `None` if
+    /// frames shouldn't run at all.
+    #[cfg_attr(not(any(feature = "gui", feature = "tty")), allow(dead_code))]
+    pub(crate) fn frame_interval(self) -> Option<f64> {
+        match self {
+            BackgroundPolicy::Block => None,
+            BackgroundPolicy::Run => Some(0.0),
+            BackgroundPolicy::Throttle(fps) => Some(1.0 / fps),
+        }
+    }
+}
+
+/// Character encodings for showing pixels on a text terminal.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum PixelMode {
+    /// Upper half block characters, one cell shows 1x2 pixels with exact
+    /// colors.
+    #[default]
+    HalfBlock,
+    /// Quadrant block characters, one cell shows 2x2 pixels in two colors.
+    Quadrant,
+    /// Braille dot characters, one cell shows 2x4 pixels in two colors.
+    Braille,
 }
 
+impl PixelMode {
+    /// Return how many pixels horizontally and vertically one char cell
+    /// shows.
+    pub fn cell_pixels(self) -> (u32, u32) {
+        match self {
+            PixelMode::HalfBlock => (1, 2),
+            PixelMode::Quadrant => (2, 2),
+            PixelMode::Braille => (2, 4),
+        }
+    }
+}
+
+/// Return the backend the application runs on.
+///
+/// When several backends are compiled in, the choice is made the first time
+/// it is needed and stays fixed afterwards. See `select_backend`.
 pub fn backend_type() -> BackendType {
     backend::backend_type()
 }
+
+/// Select the backend to run on when several have been compiled in.
+///
+/// Must be called before `run`. Without an explicit selection, the backend
+/// is read from the `NAVNI_BACKEND` environment variable (`gui`, `tty` or
+/// `headless`), and if that isn't set, the GUI backend is picked if
+/// `DISPLAY` or `WAYLAND_DISPLAY` indicate that a window can be opened and
+/// the TTY backend otherwise.
+///
+/// The choice applies to all threads. Selecting the backend that has
+/// already been chosen again does nothing.
+///
+/// Panics if the backend was not compiled in or if a different backend has
+/// already been chosen or activated.
+pub fn select_backend(backend: BackendType) {
+    backend::select(backend);
+}
@@ -4,26 +4,35 @@
 //! syslog.
 
 // TODO: WASM logging
-//#[cfg_attr(
-//    all(feature = "gui", target_arch = "wasm32"),
-//    path = "wasm_logger.rs"
-//)]
 // TODO: Windows logging
-//#[cfg_attr(
-//    all(feature = "gui", target_os = "windows"),
-//    path = "win_logger.rs"
-//)]
-#[cfg_attr(
-    all(
-        feature = "gui",
-        not(target_os = "windows"),
-        not(target_arch = "wasm32")
-    ),
-    path = "env_logger.rs"
-)]
-#[cfg_attr(
-    all(feature = "tty", not(feature = "gui"), target_os = "linux"),
-    path = "syslog_logger.rs"
-)]
+#[cfg(all(
+    feature = "gui",
+    not(target_os = "windows"),
+    not(target_arch = "wasm32")
+))]
+#[path = "env_logger.rs"]
+mod env;
+
+#[cfg(all(feature = "tty", target_os = "linux"))]
+mod syslog_logger;
+
+#[allow(dead_code)]
 mod backend;
-pub use backend::start;
+
+/// Start the logger that suits the active backend.
+pub fn start(pname: &str) {
+    match crate::backend_type() {
+        #[cfg(all(feature = "tty", target_os = "linux"))]
+        crate::BackendType::Tty => syslog_logger::start(pname),
+        #[cfg(all(
+            feature = "gui",
+            not(target_os = "windows"),
+            not(target_arch = "wasm32")
+        ))]
+        crate::BackendType::Gui | crate::BackendType::Headless => {
+            env::start(pname)
+        }
+        #[allow(unreachable_patterns)]
+        _ => backend::start(pname),
+    }
+}
@@ -1,4 +1,4 @@
 pub use crate::{
-    BackendType, CharCell, Directory, FrameCounter, Key, MouseButton,
-    MouseState, Rgba, X256Color,
+    BackendType, CharCell, Click, Directory, Event, FrameCounter, Key,
+    MouseButton, MouseState, Rgba, X256Color,
 };
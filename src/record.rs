@@ -0,0 +1,701 @@
+//! Recording and deterministic replay of application input.
+//!
+//! The recorder logs every per-frame input value the application reads from
+//! navni, the keypress, mouse state, input events, the held down keys that
+//! were queried and the values returned by `navni::now`, and saves the log into a
+//! `Directory`. Replaying the log feeds the recorded values back in place of
+//! live input, so a deterministic application will play the session back
+//! exactly as it went. When the replay log runs out, the application
+//! continues with live input.
+//!
+//! The log is a text file with one line per frame.
+use std::{cell::RefCell, collections::VecDeque, fmt, str::FromStr};
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+    ButtonState, Click, Directory, Event, Key, KeyMods, KeyTyped, MouseButton,
+    MouseState, TimedEvent,
+};
+
+const HEADER: &str = "navni-record 1";
+
+#[derive(Clone, Default, PartialEq, Debug)]
+struct Frame {
+    /// Every value `navni::now` returned during the frame, in order.
+    now: Vec<f64>,
+    keypress: KeyTyped,
+    mouse_state: MouseState,
+    /// Keys that were queried with `navni::is_down` and were down.
+    key_down: Vec<Key>,
+    events: Vec<TimedEvent>,
+}
+
+#[derive(Default)]
+enum Mode {
+    #[default]
+    Off,
+    Recording {
+        target: Option<(Directory, String)>,
+        frames: Vec<Frame>,
+        current: Box<Frame>,
+    },
+    Replaying {
+        frames: VecDeque<Frame>,
+        // Position in the `now` list of the frame at the front.
+        now_idx: usize,
+        // Most recent replayed time, for frames that never read the clock
+        // during recording.
+        last_now: f64,
+    },
+}
+
+thread_local! {
+    static MODE: RefCell<Mode> = const { RefCell::new(Mode::Off) };
+}
+
+/// Start recording input, the log will be saved into the given file in
+/// `dir` when recording is stopped or the application exits.
+///
+/// Any ongoing recording or replay is stopped.
+pub fn start_recording(dir: Directory, file_name: &str) {
+    start(Some((dir, file_name.to_owned())));
+}
+
+/// Stop recording and save the log.
+pub fn stop_recording() -> anyhow::Result<()> {
+    if !is_recording() {
+        bail!("Not recording");
+    }
+    let target = MODE.with(|m| match &mut *m.borrow_mut() {
+        Mode::Recording { target, .. } => target.take(),
+        _ => None,
+    });
+
+    let log = take_log();
+    if let Some((mut dir, name)) = target {
+        dir.write(&name, &log.to_string())?;
+    }
+    Ok(())
+}
+
+/// Start replaying a log saved by an earlier recording.
+///
+/// Any ongoing recording or replay is stopped.
+pub fn start_replay(dir: &Directory, file_name: &str) -> anyhow::Result<()> {
+    let log: Log = dir.read(file_name)?.parse()?;
+    replay(log);
+    Ok(())
+}
+
+/// Return whether input is currently being recorded.
+pub fn is_recording() -> bool {
+    MODE.with(|m| matches!(*m.borrow(), Mode::Recording { .. }))
+}
+
+/// Return whether input is currently coming from a replay log.
+pub fn is_replaying() -> bool {
+    MODE.with(|m| matches!(*m.borrow(), Mode::Replaying { .. }))
+}
+
+fn start(target: Option<(Directory, String)>) {
+    stop();
+    MODE.with(|m| {
+        *m.borrow_mut() = Mode::Recording {
+            target,
+            frames: Default::default(),
+            current: Default::default(),
+        }
+    });
+}
+
+/// Stop recording and return the log recorded so far.
+fn take_log() -> Log {
+    let Mode::Recording {
+        mut frames,
+        current,
+        ..
+    } = MODE.with(|m| m.take())
+    else {
+        return Default::default();
+    };
+
+    // The frame in progress hasn't been finished, but it may contain input
+    // from an interrupted session, so keep it.
+    frames.push(finish_frame(*current));
+    Log(frames.into())
+}
+
+fn replay(log: Log) {
+    stop();
+    if log.0.is_empty() {
+        return;
+    }
+    // Start from the earliest recorded time if the first frames have none.
+    let last_now = log.0.iter().find_map(|f| f.now.first()).copied();
+    MODE.with(|m| {
+        *m.borrow_mut() = Mode::Replaying {
+            frames: log.0,
+            now_idx: 0,
+            last_now: last_now.unwrap_or_default(),
+        }
+    });
+}
+
+// Hooks called by the navni interface functions. Each takes a function to
+// read the live value from the backend.
+
+pub(crate) fn now(live: impl FnOnce() -> f64) -> f64 {
+    MODE.with(|m| match &mut *m.borrow_mut() {
+        Mode::Off => live(),
+        Mode::Recording { current, .. } => {
+            let t = live();
+            current.now.push(t);
+            t
+        }
+        Mode::Replaying {
+            frames,
+            now_idx,
+            last_now,
+        } => {
+            // If the app is asking for the time more often than during
+            // recording, keep returning the last value.
+            if let Some(&t) = frames[0].now.get(*now_idx) {
+                *now_idx += 1;
+                *last_now = t;
+            }
+            *last_now
+        }
+    })
+}
+
+pub(crate) fn is_down(key: Key, live: impl FnOnce() -> bool) -> bool {
+    MODE.with(|m| match &mut *m.borrow_mut() {
+        Mode::Off => live(),
+        Mode::Recording { current, .. } => {
+            let ret = live();
+            if ret && !current.key_down.contains(&key) {
+                current.key_down.push(key);
+            }
+            ret
+        }
+        Mode::Replaying { frames, .. } => frames[0].key_down.contains(&key),
+    })
+}
+
+pub(crate) fn keypress(live: impl FnOnce() -> KeyTyped) -> KeyTyped {
+    MODE.with(|m| match &*m.borrow() {
+        Mode::Replaying { frames, .. } => frames[0].keypress,
+        _ => live(),
+    })
+}
+
+pub(crate) fn mouse_state(live: impl FnOnce() -> MouseState) -> MouseState {
+    MODE.with(|m| match &*m.borrow() {
+        Mode::Replaying { frames, .. } => frames[0].mouse_state,
+        _ => live(),
+    })
+}
+
+pub(crate) fn events(
+    live: impl FnOnce() -> Vec<TimedEvent>,
+) -> Vec<TimedEvent> {
+    MODE.with(|m| match &*m.borrow() {
+        Mode::Replaying { frames, .. } => frames[0].events.clone(),
+        _ => live(),
+    })
+}
+
+/// Called by the executor when the application has finished a frame.
+pub(crate) fn end_frame() {
+    MODE.with(|m| {
+        let mut mode = m.borrow_mut();
+        match &mut *mode {
+            Mode::Off => {}
+            Mode::Recording {
+                frames, current, ..
+            } => {
+                frames.push(finish_frame(*std::mem::take(current)));
+            }
+            Mode::Replaying {
+                frames, now_idx, ..
+            } => {
+                frames.pop_front();
+                *now_idx = 0;
+                if frames.is_empty() {
+                    *mode = Mode::Off;
+                }
+            }
+        }
+    });
+}
+
+/// Fill in the values that stay constant during a frame.
+fn finish_frame(mut frame: Frame) -> Frame {
+    frame.keypress = crate::backend::with(|b| b.keypress());
+    frame.mouse_state = crate::backend::with(|b| b.mouse_state());
+    frame.events = crate::backend::with(|b| b.events());
+    frame
+}
+
+/// Stop any ongoing recording or replay, called by the executor when the
+/// application has exited.
+pub(crate) fn stop() {
+    if is_recording()
+        && let Err(e) = stop_recording()
+    {
+        log::warn!("Failed to save input recording: {e}");
+    }
+    MODE.with(|m| m.take());
+}
+
+/// Input log file contents.
+#[derive(Clone, Default, PartialEq, Debug)]
+struct Log(VecDeque<Frame>);
+
+impl fmt::Display for Log {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{HEADER}")?;
+        for frame in &self.0 {
+            writeln!(f, "{frame}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Log {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+        if lines.next() != Some(HEADER) {
+            bail!("Not a navni input recording");
+        }
+        Ok(Log(lines.map(|a| a.parse()).collect::<Result<_, _>>()?))
+    }
+}
+
+// Frame line format, fields separated by tabs:
+//
+//     now values    keypress    mouse state    keys down    events...
+//
+// The now values and keys down fields are space-separated lists. Every
+// event of the frame gets a field of its own.
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let now: Vec<String> = self.now.iter().map(|t| t.to_string()).collect();
+        let down: Vec<String> =
+            self.key_down.iter().map(|k| k.to_string()).collect();
+        write!(
+            f,
+            "{}\t{}\t{}\t{}",
+            now.join(" "),
+            self.keypress,
+            mouse_to_string(&self.mouse_state),
+            down.join(" ")
+        )?;
+        for e in &self.events {
+            write!(f, "\t{}", event_to_string(e))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Frame {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split('\t').collect();
+        let [now, keypress, mouse_state, key_down, ref events @ ..] =
+            fields[..]
+        else {
+            bail!("Bad frame {s:?}");
+        };
+
+        Ok(Frame {
+            now: now
+                .split_whitespace()
+                .map(|a| a.parse())
+                .collect::<Result<_, _>>()?,
+            keypress: keypress.parse()?,
+            mouse_state: mouse_from_str(mouse_state)?,
+            key_down: key_down
+                .split_whitespace()
+                .map(|a| a.parse())
+                .collect::<Result<_, _>>()?,
+            events: events
+                .iter()
+                .map(|a| event_from_str(a))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+fn button_to_str(b: &MouseButton) -> &'static str {
+    match b {
+        MouseButton::Left => "left",
+        MouseButton::Middle => "middle",
+        MouseButton::Right => "right",
+    }
+}
+
+fn button_from_str(s: &str) -> anyhow::Result<MouseButton> {
+    match s {
+        "left" => Ok(MouseButton::Left),
+        "middle" => Ok(MouseButton::Middle),
+        "right" => Ok(MouseButton::Right),
+        _ => Err(anyhow!("Bad mouse button {s:?}")),
+    }
+}
+
+// Mouse format is the cursor position and the scroll delta followed by
+// the pressed buttons and the last press, each as a tag and a click.
+fn mouse_to_string(mouse_state: &MouseState) -> String {
+    let [x, y] = mouse_state.pos;
+    let [u, v] = mouse_state.scroll;
+    let mut ret = format!("{x} {y} {u} {v}");
+    for b in &mouse_state.buttons {
+        match b {
+            ButtonState::Up => {}
+            ButtonState::Down(c) => {
+                ret += &format!(" down {}", click_to_string(c))
+            }
+            ButtonState::Released(c) => {
+                ret += &format!(" released {}", click_to_string(c))
+            }
+        }
+    }
+    if let Some(c) = &mouse_state.last_press {
+        ret += &format!(" last {}", click_to_string(c));
+    }
+    ret
+}
+
+fn mouse_from_str(s: &str) -> anyhow::Result<MouseState> {
+    let parts: Vec<&str> = s.split(' ').collect();
+    let n = |i: usize| -> anyhow::Result<i32> {
+        Ok(parts
+            .get(i)
+            .ok_or_else(|| anyhow!("Bad mouse {s:?}"))?
+            .parse()?)
+    };
+
+    let mut ret = MouseState {
+        pos: [n(0)?, n(1)?],
+        scroll: [n(2)?, n(3)?],
+        ..Default::default()
+    };
+
+    let rest = &parts[4..];
+    if !rest.len().is_multiple_of(7) {
+        bail!("Bad mouse {s:?}");
+    }
+    for item in rest.chunks(7) {
+        let c = click_from_str(&item[1..])?;
+        match item[0] {
+            "down" => ret.buttons[c.button as usize] = ButtonState::Down(c),
+            "released" => {
+                ret.buttons[c.button as usize] = ButtonState::Released(c)
+            }
+            "last" => ret.last_press = Some(c),
+            _ => bail!("Bad mouse {s:?}"),
+        }
+    }
+    Ok(ret)
+}
+
+fn click_to_string(c: &Click) -> String {
+    let [x, y] = c.pos;
+    let mut mods = String::new();
+    for (set, m) in [
+        (c.mods.shift, 'S'),
+        (c.mods.ctrl, 'C'),
+        (c.mods.alt, 'A'),
+        (c.mods.logo, 'D'),
+    ] {
+        if set {
+            mods.push(m);
+        }
+    }
+    if mods.is_empty() {
+        mods.push('-');
+    }
+    format!(
+        "{} {x} {y} {mods} {} {}",
+        button_to_str(&c.button),
+        c.count,
+        c.time
+    )
+}
+
+fn click_from_str(parts: &[&str]) -> anyhow::Result<Click> {
+    let [button, x, y, mods, count, time] = parts else {
+        bail!("Bad click {parts:?}");
+    };
+    if *mods != "-" && !mods.chars().all(|c| "SCAD".contains(c)) {
+        bail!("Bad click {parts:?}");
+    }
+    Ok(Click {
+        pos: [x.parse()?, y.parse()?],
+        button: button_from_str(button)?,
+        mods: KeyMods {
+            shift: mods.contains('S'),
+            ctrl: mods.contains('C'),
+            alt: mods.contains('A'),
+            logo: mods.contains('D'),
+        },
+        count: count.parse()?,
+        time: time.parse()?,
+    })
+}
+
+// Event format is the timestamp, the event type and the event's values
+// separated by spaces.
+fn event_to_string(e: &TimedEvent) -> String {
+    let value = match &e.event {
+        Event::KeyDown(k) => format!("down {k}"),
+        Event::KeyRepeat(k) => format!("repeat {k}"),
+        Event::KeyUp(k) => format!("up {k}"),
+        Event::Text(c) => format!("text {}", escape(&c.to_string())),
+        Event::MouseMove([x, y]) => format!("move {x} {y}"),
+        Event::MouseDown([x, y], b) => {
+            format!("mousedown {x} {y} {}", button_to_str(b))
+        }
+        Event::MouseUp([x, y], b) => {
+            format!("mouseup {x} {y} {}", button_to_str(b))
+        }
+        Event::Scroll([x, y], [u, v]) => format!("scroll {x} {y} {u} {v}"),
+        Event::Resize => "resize".to_owned(),
+        Event::Focus(true) => "focus".to_owned(),
+        Event::Focus(false) => "unfocus".to_owned(),
+        Event::Minimize(true) => "minimize".to_owned(),
+        Event::Minimize(false) => "restore".to_owned(),
+        Event::Paste(text) => format!("paste {}", escape(text)),
+    };
+    format!("{} {value}", e.time)
+}
+
+fn event_from_str(s: &str) -> anyhow::Result<TimedEvent> {
+    let err = || anyhow!("Bad event {s:?}");
+
+    let mut parts = s.splitn(3, ' ');
+    let time: f64 = parts.next().ok_or_else(err)?.parse()?;
+    let kind = parts.next().ok_or_else(err)?;
+    let rest = parts.next().unwrap_or("");
+
+    let nums: Vec<&str> = rest.split(' ').collect();
+    let n = |i: usize| -> anyhow::Result<i32> {
+        Ok(nums.get(i).ok_or_else(err)?.parse()?)
+    };
+
+    let event = match kind {
+        "down" => Event::KeyDown(rest.parse()?),
+        "repeat" => Event::KeyRepeat(rest.parse()?),
+        "up" => Event::KeyUp(rest.parse()?),
+        "text" => {
+            let text = unescape(rest)?;
+            let mut chars = text.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Event::Text(c),
+                _ => return Err(err()),
+            }
+        }
+        "move" if nums.len() == 2 => Event::MouseMove([n(0)?, n(1)?]),
+        "mousedown" if nums.len() == 3 => {
+            Event::MouseDown([n(0)?, n(1)?], button_from_str(nums[2])?)
+        }
+        "mouseup" if nums.len() == 3 => {
+            Event::MouseUp([n(0)?, n(1)?], button_from_str(nums[2])?)
+        }
+        "scroll" if nums.len() == 4 => {
+            Event::Scroll([n(0)?, n(1)?], [n(2)?, n(3)?])
+        }
+        "resize" => Event::Resize,
+        "focus" => Event::Focus(true),
+        "unfocus" => Event::Focus(false),
+        "minimize" => Event::Minimize(true),
+        "restore" => Event::Minimize(false),
+        "paste" => Event::Paste(unescape(rest)?),
+        _ => return Err(err()),
+    };
+
+    Ok(TimedEvent::new(time, event))
+}
+
+/// Escape characters that would break the line and field structure.
+fn escape(s: &str) -> String {
+    let mut ret = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => ret.push_str("\\\\"),
+            '\t' => ret.push_str("\\t"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+fn unescape(s: &str) -> anyhow::Result<String> {
+    let mut ret = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            ret.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => ret.push('\\'),
+            Some('t') => ret.push('\t'),
+            Some('n') => ret.push('\n'),
+            Some('r') => ret.push('\r'),
+            _ => bail!("Bad escape in {s:?}"),
+        }
+    }
+    Ok(ret)
+}
+
+#[cfg(all(test, feature = "headless"))]
+mod test {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{BackendType, testing};
+
+    /// Run a session and log everything the application sees.
+    fn session(scripted: bool) -> (Vec<String>, Option<Log>) {
+        let seen = Rc::new(RefCell::new((Vec::new(), None)));
+        let out = seen.clone();
+
+        crate::select_backend(BackendType::Headless);
+        crate::run("test", async move {
+            if scripted {
+                testing::press("Ret");
+                testing::type_text("xy");
+                testing::key_down(Key::Up);
+                testing::click([5, 6], MouseButton::Right);
+            }
+
+            for _ in 0..5 {
+                crate::draw_chars(0, 0, &[]).await;
+                out.borrow_mut().0.push(format!(
+                    "{} {} {:?} {} {:?}",
+                    crate::now(),
+                    crate::keypress(),
+                    crate::mouse_state(),
+                    crate::is_down(Key::Up),
+                    crate::events(),
+                ));
+            }
+
+            if is_recording() {
+                out.borrow_mut().1 = Some(take_log());
+            }
+        });
+
+        seen.take()
+    }
+
+    #[test]
+    fn record_and_replay() {
+        start(None);
+        let (recorded, log) = session(true);
+        let log = log.unwrap();
+
+        let text = log.to_string();
+        assert_eq!(text.parse::<Log>().unwrap(), log);
+
+        // Replay with a different clock rate and without the scripted input
+        // must give the same results.
+        crate::headless::set_frame_duration(1.0);
+        replay(log);
+        let (replayed, _) = session(false);
+        assert_eq!(recorded, replayed);
+        assert!(!is_replaying());
+    }
+
+    #[test]
+    fn replay_without_clock_reads() {
+        let frame = |now: &[f64]| Frame {
+            now: now.to_vec(),
+            ..Default::default()
+        };
+        replay(Log(vec![
+            frame(&[]),
+            frame(&[1.5]),
+            frame(&[]),
+            frame(&[3.0]),
+        ]
+        .into()));
+
+        crate::select_backend(BackendType::Headless);
+        crate::run("test", async {
+            let mut times = Vec::new();
+            for _ in 0..4 {
+                times.push(crate::now());
+                times.push(crate::now());
+                crate::draw_chars(0, 0, &[]).await;
+            }
+            assert_eq!(times, vec![1.5, 1.5, 1.5, 1.5, 1.5, 1.5, 3.0, 3.0]);
+        });
+    }
+
+    #[test]
+    fn mouse_format() {
+        let shift = KeyMods {
+            shift: true,
+            ..Default::default()
+        };
+
+        let hover = MouseState {
+            pos: [1, -2],
+            ..Default::default()
+        };
+        let mut drag = hover;
+        drag.button_down(
+            MouseButton::Left,
+            shift,
+            0.5,
+            MouseState::DEFAULT_CLICK_INTERVAL,
+        );
+        let mut release = drag;
+        release.button_down(
+            MouseButton::Middle,
+            Default::default(),
+            1.25,
+            MouseState::DEFAULT_CLICK_INTERVAL,
+        );
+        release.button_up(MouseButton::Middle);
+        let mut scroll = hover;
+        scroll.scroll(0, -1);
+
+        for m in [hover, drag, release, scroll] {
+            assert_eq!(mouse_from_str(&mouse_to_string(&m)).unwrap(), m);
+        }
+    }
+
+    #[test]
+    fn event_format() {
+        for e in [
+            Event::KeyDown("C-x".parse().unwrap()),
+            Event::KeyRepeat("S-Up".parse().unwrap()),
+            Event::KeyUp("Sp".parse().unwrap()),
+            Event::Text(' '),
+            Event::Text('\t'),
+            Event::MouseMove([1, -2]),
+            Event::MouseDown([1, 2], MouseButton::Left),
+            Event::MouseUp([1, 2], MouseButton::Right),
+            Event::Scroll([1, 2], [0, -1]),
+            Event::Resize,
+            Event::Focus(false),
+            Event::Minimize(true),
+            Event::Paste("a b\\c\n\td;".to_owned()),
+        ] {
+            let e = TimedEvent::new(1.25, e);
+            let s = event_to_string(&e);
+            assert!(!s.contains(['\t', '\n']));
+            assert_eq!(event_from_str(&s).unwrap(), e);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Startup options for `navni::run`.
+use crate::WindowSettings;
+
+/// Application startup configuration.
+///
+/// Built from a window title and adjusted with the builder methods:
+///
+/// ```no_run
+/// let config = navni::RunConfig::new("My game")
+///     .window_size(800, 600)
+///     .resizable(false)
+///     .mouse_capture(false);
+/// navni::run(config, async {});
+/// ```
+///
+/// A plain title string or `WindowSettings` value converts into a config
+/// with default options.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct RunConfig {
+    pub window: WindowSettings,
+    pub gui: GuiConfig,
+    pub tty: TtyConfig,
+}
+
+/// Options only used by the GUI backend.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct GuiConfig {
+    /// Whether the user can resize the window.
+    pub resizable: bool,
+    /// Use the full resolution of high-DPI displays.
+    pub high_dpi: bool,
+}
+
+impl Default for GuiConfig {
+    fn default() -> Self {
+        GuiConfig {
+            resizable: true,
+            high_dpi: false,
+        }
+    }
+}
+
+/// Options only used by the TTY backend.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TtyConfig {
+    /// Draw on the terminal's alternate screen so the terminal contents are
+    /// restored on exit.
+    pub alternate_screen: bool,
+    /// Capture mouse input from the terminal.
+    pub mouse_capture: bool,
+    /// Ask the terminal for progressive keyboard enhancement, which gives
+    /// key release events and unambiguous modifier keys on terminals that
+    /// support it.
+    pub keyboard_enhancement: bool,
+}
+
+impl Default for TtyConfig {
+    fn default() -> Self {
+        TtyConfig {
+            alternate_screen: true,
+            mouse_capture: true,
+            keyboard_enhancement: true,
+        }
+    }
+}
+
+impl RunConfig {
+    pub fn new(title: impl Into<String>) -> Self {
+        WindowSettings::new(title).into()
+    }
+
+    /// Set the initial window size in pixels, GUI only.
+    pub fn window_size(mut self, w: u32, h: u32) -> Self {
+        self.window.size = (w, h);
+        self
+    }
+
+    /// Start in fullscreen mode, GUI only.
+    pub fn fullscreen(mut self, fullscreen: bool) -> Self {
+        self.window.fullscreen = fullscreen;
+        self
+    }
+
+    /// Show the operating system mouse cursor, GUI only.
+    pub fn show_cursor(mut self, show: bool) -> Self {
+        self.window.show_cursor = show;
+        self
+    }
+
+    /// Let the user resize the window, GUI only.
+    pub fn resizable(mut self, resizable: bool) -> Self {
+        self.gui.resizable = resizable;
+        self
+    }
+
+    /// Use the full resolution of high-DPI displays, GUI only.
+    pub fn high_dpi(mut self, high_dpi: bool) -> Self {
+        self.gui.high_dpi = high_dpi;
+        self
+    }
+
+    /// Draw on the terminal's alternate screen, TTY only.
+    pub fn alternate_screen(mut self, alternate_screen: bool) -> Self {
+        self.tty.alternate_screen = alternate_screen;
+        self
+    }
+
+    /// Capture mouse input from the terminal, TTY only.
+    pub fn mouse_capture(mut self, mouse_capture: bool) -> Self {
+        self.tty.mouse_capture = mouse_capture;
+        self
+    }
+
+    /// Use progressive keyboard enhancement if the terminal supports it,
+    /// TTY only.
+    pub fn keyboard_enhancement(mut self, keyboard_enhancement: bool) -> Self {
+        self.tty.keyboard_enhancement = keyboard_enhancement;
+        self
+    }
+}
+
+impl From<WindowSettings> for RunConfig {
+    fn from(window: WindowSettings) -> Self {
+        RunConfig {
+            window,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&str> for RunConfig {
+    fn from(title: &str) -> Self {
+        RunConfig::new(title)
+    }
+}
+
+impl From<String> for RunConfig {
+    fn from(title: String) -> Self {
+        RunConfig::new(title)
+    }
+}
@@ -0,0 +1,333 @@
+//! Render drawn buffers into text and images and compare them to golden
+//! files.
+//!
+//! The image rasterization follows the GUI backend's font lookup and color
+//! modulation, so the images match what the GUI would draw at 1x zoom. Use
+//! these with the headless backend to assert on screen contents in tests.
+//!
+//! Golden files are compared with `check_text` and `check_image`. When the
+//! `NAVNI_BLESS` environment variable is set, mismatching or missing golden
+//! files are overwritten with the current output instead of failing.
+use std::{fmt::Write, path::Path};
+
+use anyhow::{Result, bail};
+use image::RgbaImage;
+
+use crate::{CharCell, FontSheet, Rgba, X256Color};
+
+/// Render a character buffer as plain text, one line per row.
+///
+/// Null chars are shown as spaces.
+pub fn text(w: u32, h: u32, buffer: &[CharCell]) -> String {
+    assert!(buffer.len() == (w * h) as usize);
+
+    let mut ret = String::with_capacity(((w + 1) * h) as usize);
+    for row in buffer.chunks(w.max(1) as usize) {
+        ret.extend(row.iter().map(|&c| display_char(c)));
+        ret.push('\n');
+    }
+    ret
+}
+
+/// Render a character buffer as text with ANSI color escapes.
+///
+/// Colors are mapped to terminal attributes the same way the TTY backend
+/// does it. Every line ends with an attribute reset.
+pub fn ansi(w: u32, h: u32, buffer: &[CharCell]) -> String {
+    assert!(buffer.len() == (w * h) as usize);
+
+    let mut ret = String::new();
+    for row in buffer.chunks(w.max(1) as usize) {
+        let mut prev: Option<CharCell> = None;
+        for &cell in row {
+            if prev.is_none_or(|p| {
+                p.foreground != cell.foreground
+                    || p.background != cell.background
+            }) {
+                ret.push_str(&sgr(cell));
+            }
+            prev = Some(cell);
+            ret.push(display_char(cell));
+        }
+        ret.push_str("\x1b[0m\n");
+    }
+    ret
+}
+
+/// Rasterize a character buffer into an image using a font sheet and the
+/// system color palette.
+pub fn chars_image(
+    w: u32,
+    h: u32,
+    buffer: &[CharCell],
+    font: &FontSheet,
+    palette: &[Rgba; 16],
+) -> RgbaImage {
+    assert!(buffer.len() == (w * h) as usize);
+
+    let (fw, fh) = font.glyph_size();
+    let lookup = font.char_lookup();
+    let mut ret = RgbaImage::new(w * fw, h * fh);
+
+    for (i, cell) in buffer.iter().enumerate() {
+        let (x, y) = (i as u32 % w, i as u32 / w);
+        let glyph = lookup[cell.c as usize] as u32;
+        let (gx, gy) = (glyph % 16 * fw, glyph / 16 * fh);
+        let fore = cell.foreground.resolve(palette);
+        let back = cell.background.resolve(palette);
+
+        for v in 0..fh {
+            for u in 0..fw {
+                let texel = font.image.get_pixel(gx + u, gy + v);
+                ret.put_pixel(
+                    x * fw + u,
+                    y * fh + v,
+                    modulate(texel.0, fore, back).into(),
+                );
+            }
+        }
+    }
+
+    ret
+}
+
+/// Convert a pixel buffer into an image.
+pub fn pixels_image(w: u32, h: u32, buffer: &[Rgba]) -> RgbaImage {
+    assert!(buffer.len() == (w * h) as usize);
+
+    RgbaImage::from_fn(w, h, |x, y| buffer[(x + y * w) as usize].into())
+}
+
+/// Compare text against a golden file.
+///
+/// On mismatch the text is written next to the golden file with an
+/// `.actual` extension prefix, eg. `screen.txt` gets `screen.actual.txt`.
+pub fn check_text(path: impl AsRef<Path>, text: &str) -> Result<()> {
+    let path = path.as_ref();
+
+    let expected = std::fs::read_to_string(path).ok();
+    if expected.as_deref() == Some(text) {
+        return Ok(());
+    }
+    if is_blessing() {
+        return write_golden(path, text.as_bytes());
+    }
+
+    let actual = sibling(path, "actual");
+    std::fs::write(&actual, text)?;
+
+    let Some(expected) = expected else {
+        bail!(
+            "Missing golden file {path:?}, output written to {actual:?}, \
+             set NAVNI_BLESS=1 to accept"
+        );
+    };
+
+    let line = expected
+        .lines()
+        .zip(text.lines())
+        .position(|(a, b)| a != b)
+        .unwrap_or(expected.lines().count().min(text.lines().count()));
+    bail!(
+        "Text differs from {path:?} at line {}, output written to {actual:?}",
+        line + 1
+    );
+}
+
+/// Compare an image against a golden PNG file.
+///
+/// On mismatch the image is written next to the golden file with an
+/// `.actual` extension prefix and a difference image is written with a
+/// `.diff` prefix. Differing pixels are red in the difference image.
+pub fn check_image(path: impl AsRef<Path>, image: &RgbaImage) -> Result<()> {
+    let path = path.as_ref();
+
+    let expected = image::open(path).ok().map(|a| a.to_rgba8());
+    if expected.as_ref() == Some(image) {
+        return Ok(());
+    }
+    if is_blessing() {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        image.save(path)?;
+        return Ok(());
+    }
+
+    let actual = sibling(path, "actual");
+    image.save(&actual)?;
+
+    let Some(expected) = expected else {
+        bail!(
+            "Missing golden file {path:?}, output written to {actual:?}, \
+             set NAVNI_BLESS=1 to accept"
+        );
+    };
+
+    let diff_path = sibling(path, "diff");
+    let (diff, count) = diff(&expected, image);
+    diff.save(&diff_path)?;
+
+    bail!(
+        "Image differs from {path:?} in {count} pixels, \
+         output written to {actual:?}, difference to {diff_path:?}"
+    );
+}
+
+fn is_blessing() -> bool {
+    std::env::var_os("NAVNI_BLESS").is_some_and(|a| !a.is_empty())
+}
+
+fn write_golden(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, data)?;
+    Ok(())
+}
+
+/// Path with an extra tag inserted before the extension.
+fn sibling(path: &Path, tag: &str) -> std::path::PathBuf {
+    match path.extension() {
+        Some(ext) => {
+            path.with_extension(format!("{tag}.{}", ext.to_string_lossy()))
+        }
+        None => path.with_extension(tag),
+    }
+}
+
+/// Build a difference image and count the differing pixels.
+///
+/// Matching pixels are shown as dimmed grayscale and differing ones as pure
+/// red. If the sizes differ, the area covered by only one image counts as
+/// differing.
+fn diff(a: &RgbaImage, b: &RgbaImage) -> (RgbaImage, usize) {
+    let (w, h) = (a.width().max(b.width()), a.height().max(b.height()));
+    let mut count = 0;
+
+    let ret = RgbaImage::from_fn(w, h, |x, y| {
+        match (a.get_pixel_checked(x, y), b.get_pixel_checked(x, y)) {
+            (Some(p), Some(q)) if p == q => {
+                let c = Rgba::from(*p).greyscale() / 3;
+                image::Rgba([c, c, c, 0xff])
+            }
+            _ => {
+                count += 1;
+                image::Rgba([0xff, 0, 0, 0xff])
+            }
+        }
+    });
+
+    (ret, count)
+}
+
+fn display_char(cell: CharCell) -> char {
+    match char::from_u32(cell.c as u32) {
+        Some('\0') => ' ',
+        Some(c) => c,
+        None => char::REPLACEMENT_CHARACTER,
+    }
+}
+
+/// Select graphic rendition escape for cell colors.
+fn sgr(cell: CharCell) -> String {
+    // System background color as cell foreground marks inverse display,
+    // system foreground as foreground marks no color and system colors 8-15
+    // are styled bold.
+    let is_inverse = cell.foreground == X256Color::BACKGROUND
+        && cell.background != X256Color::BACKGROUND;
+    let foreground = if is_inverse {
+        cell.background
+    } else {
+        cell.foreground
+    };
+
+    let mut ret = String::from("\x1b[0");
+    if is_inverse {
+        ret.push_str(";7");
+    } else if cell.background != X256Color::BACKGROUND {
+        write!(ret, ";48;5;{}", cell.background.0).unwrap();
+    }
+    if foreground != X256Color::FOREGROUND
+        && foreground != X256Color::BOLD_FOREGROUND
+    {
+        write!(ret, ";38;5;{}", foreground.0).unwrap();
+    }
+    if (8..16).contains(&foreground.0) && !is_inverse {
+        ret.push_str(";1");
+    }
+    ret.push('m');
+    ret
+}
+
+/// Blend foreground and background with a font texel the same way the GUI
+/// character shader does.
+fn modulate(texel: [u8; 4], fore: Rgba, back: Rgba) -> Rgba {
+    fn mix(t: u8, f: u8, b: u8) -> u8 {
+        let (t, f, b) = (t as u32, f as u32, b as u32);
+        ((t * f + (255 - t) * b + 127) / 255) as u8
+    }
+
+    Rgba::new(
+        mix(texel[0], fore.r, back.r),
+        mix(texel[1], fore.g, back.g),
+        mix(texel[2], fore.b, back.b),
+        mix(texel[3], fore.a, back.a),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn palette() -> [Rgba; 16] {
+        std::array::from_fn(|i| X256Color(i as u8).into())
+    }
+
+    #[test]
+    fn text_render() {
+        let buf: Vec<CharCell> = "ab\0d".chars().map(CharCell::c).collect();
+        assert_eq!(text(2, 2, &buf), "ab\n d\n");
+
+        let buf = [CharCell::c('x').col(X256Color::RED), CharCell::c('y')];
+        assert_eq!(ansi(2, 1, &buf), "\x1b[0;38;5;9;1mx\x1b[0my\x1b[0m\n");
+    }
+
+    #[test]
+    fn rasterize() {
+        let font = FontSheet::default();
+        let (fw, fh) = font.glyph_size();
+        let buf = [
+            CharCell::new('█', X256Color::RED, X256Color::NAVY),
+            CharCell::new(' ', X256Color::RED, X256Color::NAVY),
+        ];
+
+        let img = chars_image(2, 1, &buf, &font, &palette());
+        assert_eq!(img.dimensions(), (2 * fw, fh));
+        assert_eq!(Rgba::from(*img.get_pixel(1, 1)), X256Color::RED.into());
+        assert_eq!(
+            Rgba::from(*img.get_pixel(fw + 1, 1)),
+            X256Color::NAVY.into()
+        );
+    }
+
+    #[test]
+    fn golden_mismatch() {
+        let dir = std::env::temp_dir()
+            .join(format!("navni-snapshot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("img.png");
+
+        let img = pixels_image(2, 1, &[Rgba::RED, Rgba::BLUE]);
+        img.save(&path).unwrap();
+        assert!(check_image(&path, &img).is_ok());
+
+        let img2 = pixels_image(2, 1, &[Rgba::RED, Rgba::GREEN]);
+        assert!(check_image(&path, &img2).is_err());
+        let diff = image::open(dir.join("img.diff.png")).unwrap().to_rgba8();
+        assert_eq!(diff.get_pixel(1, 0).0, [0xff, 0, 0, 0xff]);
+        assert_ne!(diff.get_pixel(0, 0).0, [0xff, 0, 0, 0xff]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,285 @@
+//! Synthetic input for driving applications without a human.
+//!
+//! Inputs are queued and then fed into the backend's regular input state
+//! when the current frame ends, so the application sees them after its next
+//! `draw_*` call has been awaited, just like real input. Keypresses come out
+//! one per frame in the order they were queued, `navni::events` reports all
+//! the queued inputs on the next frame.
+//!
+//! The queue is thread-local and must be filled from the thread that runs
+//! the application.
+use std::{cell::RefCell, collections::VecDeque};
+
+use rustc_hash::FxHashSet as HashSet;
+
+use crate::{
+    Event, Key, KeyMods, KeyTyped, MouseButton, MouseState, TimedEvent,
+};
+
+enum Input {
+    Press(KeyTyped),
+    KeyDown(Key),
+    KeyUp(Key),
+    MouseMove([i32; 2]),
+    MouseDown(MouseButton),
+    MouseUp(MouseButton),
+    Scroll(i32, i32),
+    /// Event that doesn't change backend input state.
+    Event(Event),
+}
+
+thread_local! {
+    static QUEUE: RefCell<VecDeque<Input>> = const {
+        RefCell::new(VecDeque::new())
+    };
+}
+
+fn push(input: Input) {
+    QUEUE.with(|q| q.borrow_mut().push_back(input));
+}
+
+/// Queue a keypress given in the `KeyTyped` string format, eg. `"Ret"` or
+/// `"C-x"`.
+///
+/// Panics if the string isn't a valid key.
+pub fn press(code: &str) {
+    let typed = code
+        .parse()
+        .unwrap_or_else(|e| panic!("testing::press: bad key {code:?}: {e}"));
+    press_typed(typed);
+}
+
+/// Queue a keypress.
+pub fn press_typed(typed: KeyTyped) {
+    push(Input::Press(typed));
+}
+
+/// Queue a keypress for every character in the text.
+pub fn type_text(text: &str) {
+    for c in text.chars() {
+        press_typed(KeyTyped::new(Key::Char(c), Default::default(), false));
+    }
+}
+
+/// Start holding down a key, will be reported by `navni::is_down`.
+pub fn key_down(key: Key) {
+    push(Input::KeyDown(key));
+}
+
+/// Release a key held down with `key_down`.
+pub fn key_up(key: Key) {
+    push(Input::KeyUp(key));
+}
+
+/// Move mouse cursor to a position in the coordinates of the drawn buffer.
+pub fn mouse_move(pos: [i32; 2]) {
+    push(Input::MouseMove(pos));
+}
+
+/// Press down a mouse button at the current cursor position.
+pub fn mouse_down(button: MouseButton) {
+    push(Input::MouseDown(button));
+}
+
+/// Release a mouse button.
+pub fn mouse_up(button: MouseButton) {
+    push(Input::MouseUp(button));
+}
+
+/// Move the mouse to position and press and release a button there.
+///
+/// The application will see the button released in `mouse_state` on the
+/// next frame.
+pub fn click(pos: [i32; 2], button: MouseButton) {
+    mouse_move(pos);
+    mouse_down(button);
+    mouse_up(button);
+}
+
+/// Scroll the mouse wheel `u` steps horizontally and `v` steps vertically.
+pub fn scroll(u: i32, v: i32) {
+    push(Input::Scroll(u, v));
+}
+
+/// Paste text into the application, it arrives as an `Event::Paste`.
+pub fn paste(text: &str) {
+    push_event(Event::Paste(text.to_owned()));
+}
+
+/// Queue an event that's only reported by `navni::events`.
+pub(crate) fn push_event(event: Event) {
+    push(Input::Event(event));
+}
+
+/// Feed queued inputs into backend input state, called by backends at the
+/// end of each frame.
+///
+/// The generated events get the timestamp `now`.
+#[cfg_attr(
+    not(any(feature = "gui", feature = "tty", feature = "headless")),
+    allow(dead_code)
+)]
+pub(crate) fn apply(
+    now: f64,
+    click_interval: f64,
+    keypress: &mut VecDeque<KeyTyped>,
+    key_down: &mut HashSet<Key>,
+    mouse_state: &mut MouseState,
+    events: &mut Vec<TimedEvent>,
+) {
+    QUEUE.with(|q| {
+        for input in q.borrow_mut().drain(..) {
+            let mut event = |e| events.push(TimedEvent::new(now, e));
+            match input {
+                Input::Press(typed) => {
+                    keypress.push_back(typed);
+                    event(Event::KeyDown(typed));
+                    if let Some(c) = typed.text() {
+                        event(Event::Text(c));
+                    }
+                }
+                Input::KeyDown(key) => {
+                    let key = key.char_to_lowercase();
+                    key_down.insert(key);
+                    event(Event::KeyDown(KeyTyped::new(
+                        key,
+                        Default::default(),
+                        false,
+                    )));
+                }
+                Input::KeyUp(key) => {
+                    let key = key.char_to_lowercase();
+                    key_down.remove(&key);
+                    event(Event::KeyUp(KeyTyped::new(
+                        key,
+                        Default::default(),
+                        false,
+                    )));
+                }
+                Input::MouseMove(pos) => {
+                    *mouse_state.cursor_pos_mut() = pos;
+                    event(Event::MouseMove(pos));
+                }
+                Input::MouseDown(button) => {
+                    mouse_state.button_down(
+                        button,
+                        KeyMods::from_held(key_down),
+                        now,
+                        click_interval,
+                    );
+                    event(Event::MouseDown(mouse_state.cursor_pos(), button));
+                }
+                Input::MouseUp(button) => {
+                    mouse_state.button_up(button);
+                    event(Event::MouseUp(mouse_state.cursor_pos(), button));
+                }
+                Input::Scroll(u, v) => {
+                    mouse_state.scroll(u, v);
+                    event(Event::Scroll(mouse_state.cursor_pos(), [u, v]));
+                }
+                Input::Event(e) => event(e),
+            }
+        }
+    });
+}
+
+#[cfg(all(test, feature = "headless"))]
+mod test {
+    use super::*;
+    use crate::BackendType;
+
+    #[test]
+    fn scripted_input() {
+        crate::select_backend(BackendType::Headless);
+        crate::run("test", async {
+            let buf = vec![Default::default(); 80 * 24];
+
+            press("Ret");
+            type_text("ab");
+            key_down(Key::Up);
+            crate::draw_chars(80, 24, &buf).await;
+
+            assert!(crate::keypress().is("Ret"));
+            assert!(crate::is_down(Key::Up));
+            crate::draw_chars(80, 24, &buf).await;
+
+            assert!(crate::keypress().is("a"));
+            key_down(Key::Shift);
+            click([3, 4], MouseButton::Left);
+            key_up(Key::Shift);
+            crate::draw_chars(80, 24, &buf).await;
+
+            assert!(crate::keypress().is("b"));
+            let click =
+                crate::mouse_state().release(MouseButton::Left).unwrap();
+            assert_eq!(click.pos, [3, 4]);
+            assert!(click.mods.shift);
+            key_up(Key::Up);
+            crate::draw_chars(80, 24, &buf).await;
+
+            assert!(!crate::keypress().is_some());
+            assert!(!crate::is_down(Key::Up));
+            assert!(crate::mouse_state().is_hover());
+            assert_eq!(crate::mouse_state().cursor_pos(), [3, 4]);
+        });
+    }
+
+    #[test]
+    fn event_stream() {
+        crate::select_backend(BackendType::Headless);
+        crate::run("test", async {
+            type_text("ab");
+            // Chords aren't text input.
+            press("A-x");
+            press("KP5");
+            click([3, 4], MouseButton::Left);
+            crate::draw_chars(0, 0, &[]).await;
+
+            // Everything arrives on the same frame even though keypresses
+            // are handed out one at a time.
+            let events: Vec<Event> =
+                crate::events().into_iter().map(|e| e.event).collect();
+            assert_eq!(
+                events,
+                vec![
+                    Event::KeyDown("a".parse().unwrap()),
+                    Event::Text('a'),
+                    Event::KeyDown("b".parse().unwrap()),
+                    Event::Text('b'),
+                    Event::KeyDown("A-x".parse().unwrap()),
+                    Event::KeyDown("KP5".parse().unwrap()),
+                    Event::Text('5'),
+                    Event::MouseMove([3, 4]),
+                    Event::MouseDown([3, 4], MouseButton::Left),
+                    Event::MouseUp([3, 4], MouseButton::Left),
+                ]
+            );
+            assert!(crate::events().iter().all(|e| e.time == crate::now()));
+            assert!(crate::keypress().is("a"));
+
+            crate::draw_chars(0, 0, &[]).await;
+            assert!(crate::events().is_empty());
+            assert!(crate::keypress().is("b"));
+        });
+    }
+
+    #[test]
+    fn click_interval() {
+        crate::select_backend(BackendType::Headless);
+        crate::run("test", async {
+            let mut counts = Vec::new();
+            for interval in [0.5, 0.01] {
+                crate::set_click_interval(interval);
+                for _ in 0..2 {
+                    click([0, 0], MouseButton::Left);
+                    crate::draw_chars(0, 0, &[]).await;
+                    let state = crate::mouse_state();
+                    counts
+                        .push(state.release(MouseButton::Left).unwrap().count);
+                }
+            }
+            // The second click of the slow setting starts a new sequence.
+            assert_eq!(counts, vec![1, 2, 1, 1]);
+        });
+    }
+}
@@ -1,94 +1,144 @@
 //! TTY terminal backend.
-use std::{future::Future, sync::Mutex};
+use std::sync::Mutex;
 
-use crate::{FontSheet, FrameFuture, KeyTyped, prelude::*};
+use crate::{
+    Backend, BackgroundPolicy, Executor, KeyTyped, PixelMode, RunConfig,
+    TimedEvent, prelude::*,
+};
 
+mod color_depth;
 mod event;
+mod graphics;
+mod palette;
 mod runtime;
+mod term_cell;
 
-pub fn run(_window_title: &str, amain: impl Future<Output = ()> + 'static) {
-    unsafe {
-        runtime::FUTURE = Some(Box::pin(amain));
-    }
+/// TTY terminal backend, selected with `BackendType::Tty`.
+pub(crate) struct Tty;
 
-    runtime::RUNTIME
-        .set(Mutex::new(runtime::Runtime::new()))
-        .map_err(|_| panic!("backend initialized twice"))
-        .unwrap();
+impl Backend for Tty {
+    fn run(&self, config: &RunConfig, mut executor: Executor) {
+        runtime::RUNTIME
+            .set(Mutex::new(runtime::Runtime::new(&config.tty)))
+            .map_err(|_| panic!("backend initialized twice"))
+            .unwrap();
+        runtime::with(|r| r.set_title(&config.window.title));
 
-    loop {
         // Poll on the application future, this moves application logic
         // forward to the point where it awaits for frame change.
         //
         // If the future completes, the application run has ended and
         // we should quit.
-        if unsafe { crate::exec::poll(runtime::FUTURE.as_mut().unwrap()) }
-            .is_some()
-        {
-            break;
+        while executor.poll().is_pending() {
+            runtime::with(|r| {
+                r.keypress.pop_front();
+                r.mouse_state.frame_update();
+                r.events.clear();
+                r.process_events();
+                crate::testing::apply(
+                    now(),
+                    r.click_interval,
+                    &mut r.keypress,
+                    &mut r.key_down,
+                    &mut r.mouse_state,
+                    &mut r.events,
+                );
+            });
         }
 
+        runtime::cleanup();
+    }
+
+    // Fonts and window geometry are no-ops on TTY.
+
+    fn set_window_title(&self, title: &str) {
+        runtime::with(|r| r.set_title(title));
+    }
+
+    fn set_mouse_capture(&self, capture: bool) {
+        runtime::with(|r| r.set_mouse_capture(capture));
+    }
+
+    fn set_palette(&self, palette: &[Rgba; 16]) {
+        // Keys typed while the terminal was being queried are passed on.
+        let input = palette::set(palette);
         runtime::with(|r| {
-            r.keypress.pop_front();
-            r.mouse_state.frame_update();
-            r.process_events();
+            for key in event::decode_keys(&input) {
+                r.process_event(crossterm::event::Event::Key(key));
+            }
         });
     }
 
-    runtime::cleanup();
-}
+    fn draw_pixels(&self, w: u32, h: u32, buffer: &[Rgba]) {
+        runtime::with(|r| r.draw_pixels(w, h, buffer));
+    }
 
-pub fn set_font(_sheet: &FontSheet) {
-    // No-op on TTY
-}
+    fn draw_chars(&self, w: u32, h: u32, buffer: &[CharCell]) {
+        runtime::with(|r| r.draw_chars(w, h, buffer));
+    }
 
-pub fn set_palette(_palette: &[Rgba; 16]) {
-    // No-op on TTY
-}
+    fn set_pixel_mode(&self, mode: PixelMode) {
+        runtime::with(|r| r.set_pixel_mode(mode));
+    }
 
-pub fn draw_pixels(w: u32, h: u32, buffer: &[crate::Rgba]) -> FrameFuture {
-    runtime::with(|r| r.draw_pixels(w, h, buffer));
-    FrameFuture::default()
-}
+    fn pixel_resolution(&self) -> (u32, u32) {
+        runtime::with(|r| r.pixel_resolution())
+    }
 
-pub fn draw_chars(w: u32, h: u32, buffer: &[crate::CharCell]) -> FrameFuture {
-    runtime::with(|r| r.draw_chars(w, h, buffer));
-    FrameFuture::default()
-}
+    fn char_resolution(&self, _max_w: u32, _max_h: u32) -> (u32, u32) {
+        runtime::with(|r| r.char_resolution())
+    }
 
-pub fn pixel_resolution() -> (u32, u32) {
-    runtime::with(|r| r.pixel_resolution())
-}
+    fn now(&self) -> f64 {
+        now()
+    }
 
-pub fn char_resolution(_max_w: u32, _max_h: u32) -> (u32, u32) {
-    runtime::with(|r| r.char_resolution())
-}
+    fn is_down(&self, key: Key) -> bool {
+        runtime::with(|r| r.is_down(key))
+    }
 
-pub fn now() -> f64 {
-    use std::time::{SystemTime, UNIX_EPOCH};
+    fn keypress(&self) -> KeyTyped {
+        runtime::with(|r| r.keypress.front().copied().unwrap_or_default())
+    }
 
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs_f64()
-}
+    fn mouse_state(&self) -> MouseState {
+        runtime::with(|r| r.mouse_state)
+    }
 
-pub fn sleep(seconds: f64) {
-    std::thread::sleep(std::time::Duration::from_secs_f64(seconds));
-}
+    fn events(&self) -> Vec<TimedEvent> {
+        runtime::with(|r| r.events.clone())
+    }
 
-pub fn is_down(key: Key) -> bool {
-    runtime::with(|r| r.is_down(key))
-}
+    fn is_focused(&self) -> bool {
+        runtime::with(|r| r.focused)
+    }
 
-pub fn keypress() -> KeyTyped {
-    runtime::with(|r| r.keypress.front().copied().unwrap_or_default())
-}
+    fn set_background_policy(&self, policy: BackgroundPolicy) {
+        runtime::with(|r| r.background_policy = policy);
+    }
+
+    fn set_click_interval(&self, seconds: f64) {
+        runtime::with(|r| r.click_interval = seconds);
+    }
+
+    fn clipboard_get(&self) -> Option<String> {
+        runtime::with(|r| r.clipboard.clone())
+    }
 
-pub fn mouse_state() -> MouseState {
-    runtime::with(|r| r.mouse_state)
+    fn clipboard_set(&self, text: &str) {
+        runtime::with(|r| r.clipboard_set(text));
+    }
+
+    fn backend_type(&self) -> BackendType {
+        BackendType::Tty
+    }
 }
 
-pub fn backend_type() -> BackendType {
-    BackendType::Tty
+fn now() -> f64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64()
 }
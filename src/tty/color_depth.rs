@@ -0,0 +1,199 @@
+//! Terminal color capability detection.
+use std::path::PathBuf;
+
+/// How many colors the terminal can show.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub enum ColorDepth {
+    /// No colors, only reverse video and bold.
+    Mono,
+    /// The 8 basic ANSI colors.
+    Ansi8,
+    /// The basic colors and their bright variants.
+    Ansi16,
+    /// The xterm 256 color palette.
+    X256,
+    /// 24-bit RGB colors.
+    TrueColor,
+}
+
+impl ColorDepth {
+    /// Detect color depth from the environment and the terminfo database.
+    pub fn detect() -> Self {
+        Self::detect_from(|name| std::env::var(name).ok(), terminfo_colors)
+    }
+
+    fn detect_from(
+        var: impl Fn(&str) -> Option<String>,
+        terminfo: impl Fn(&str) -> Option<i32>,
+    ) -> Self {
+        // See <https://no-color.org/>.
+        if var("NO_COLOR").is_some_and(|a| !a.is_empty()) {
+            return ColorDepth::Mono;
+        }
+
+        if var("COLORTERM")
+            .is_some_and(|a| matches!(a.as_str(), "truecolor" | "24bit"))
+        {
+            return ColorDepth::TrueColor;
+        }
+
+        let Some(term) = var("TERM").filter(|a| !a.is_empty()) else {
+            // No terminal type, assume a modern terminal.
+            return ColorDepth::X256;
+        };
+
+        if term == "dumb" {
+            return ColorDepth::Mono;
+        }
+
+        if let Some(colors) = terminfo(&term) {
+            return Self::from_colors(colors);
+        }
+
+        if term.contains("256color") {
+            ColorDepth::X256
+        } else if term == "linux" || term.starts_with("vt") {
+            ColorDepth::Ansi8
+        } else if term.contains("mono") {
+            ColorDepth::Mono
+        } else {
+            ColorDepth::X256
+        }
+    }
+
+    fn from_colors(colors: i32) -> Self {
+        match colors {
+            0x1000000.. => ColorDepth::TrueColor,
+            256.. => ColorDepth::X256,
+            16.. => ColorDepth::Ansi16,
+            8.. => ColorDepth::Ansi8,
+            _ => ColorDepth::Mono,
+        }
+    }
+}
+
+/// Look up the `colors` capability of a terminal from the terminfo database.
+fn terminfo_colors(term: &str) -> Option<i32> {
+    let first = term.chars().next()?;
+
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    if let Some(dir) = std::env::var_os("TERMINFO") {
+        dirs.push(dir.into());
+    }
+    if let Some(home) = std::env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    if let Some(list) = std::env::var_os("TERMINFO_DIRS") {
+        dirs.extend(std::env::split_paths(&list));
+    }
+    dirs.extend(
+        ["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo"]
+            .map(PathBuf::from),
+    );
+
+    dirs.iter().find_map(|dir| {
+        // Entries are under either the first letter or its hex code.
+        [first.to_string(), format!("{:x}", first as u32)]
+            .iter()
+            .find_map(|sub| std::fs::read(dir.join(sub).join(term)).ok())
+            .and_then(|data| parse_colors(&data))
+    })
+}
+
+/// Read the `colors` number from a compiled terminfo entry.
+fn parse_colors(data: &[u8]) -> Option<i32> {
+    // Index of the max_colors capability in the numbers section.
+    const COLORS: usize = 13;
+
+    let short = |i: usize| -> Option<i32> {
+        let b = data.get(i * 2..i * 2 + 2)?;
+        Some(i16::from_le_bytes([b[0], b[1]]) as i32)
+    };
+
+    // Legacy format has 16-bit numbers, extended format has 32-bit ones.
+    let number_size = match short(0)? {
+        0o432 => 2,
+        0o1036 => 4,
+        _ => return None,
+    };
+    let (names_size, bools_count, numbers_count) =
+        (short(1)? as usize, short(2)? as usize, short(3)? as usize);
+
+    if COLORS >= numbers_count {
+        return None;
+    }
+
+    // Numbers start at an even offset after the header, names and bools.
+    let numbers = (12 + names_size + bools_count).next_multiple_of(2);
+    let pos = numbers + COLORS * number_size;
+    let bytes = data.get(pos..pos + number_size)?;
+
+    let colors = if number_size == 2 {
+        i16::from_le_bytes([bytes[0], bytes[1]]) as i32
+    } else {
+        i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    };
+
+    // Negative values mean the capability is absent.
+    (colors >= 0).then_some(colors)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn detect(vars: &[(&str, &str)], terminfo: Option<i32>) -> ColorDepth {
+        ColorDepth::detect_from(
+            |name| {
+                vars.iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| v.to_string())
+            },
+            |_| terminfo,
+        )
+    }
+
+    #[test]
+    fn detection() {
+        use ColorDepth::*;
+
+        assert_eq!(detect(&[("TERM", "linux")], Some(8)), Ansi8);
+        assert_eq!(detect(&[("TERM", "linux")], None), Ansi8);
+        assert_eq!(detect(&[("TERM", "xterm-256color")], None), X256);
+        assert_eq!(detect(&[("TERM", "xterm")], Some(8)), Ansi8);
+        assert_eq!(detect(&[("TERM", "dumb")], None), Mono);
+        assert_eq!(
+            detect(&[("TERM", "xterm"), ("COLORTERM", "truecolor")], Some(8)),
+            TrueColor
+        );
+        assert_eq!(
+            detect(&[("TERM", "xterm-256color"), ("NO_COLOR", "1")], None),
+            Mono
+        );
+        assert_eq!(
+            detect(&[("TERM", "xterm-direct")], Some(0x1000000)),
+            TrueColor
+        );
+    }
+
+    #[test]
+    fn terminfo_parsing() {
+        // Legacy format entry with a two-byte name section, one boolean and
+        // 14 numbers, colors is the last one.
+        let mut data = Vec::new();
+        for n in [0o432, 2, 1, 14, 0, 0] {
+            data.extend_from_slice(&(n as i16).to_le_bytes());
+        }
+        data.extend_from_slice(b"x\0");
+        // Boolean and padding to even offset.
+        data.extend_from_slice(&[1, 0]);
+        for _ in 0..13 {
+            data.extend_from_slice(&(-1i16).to_le_bytes());
+        }
+        data.extend_from_slice(&16i16.to_le_bytes());
+
+        assert_eq!(parse_colors(&data), Some(16));
+        assert_eq!(parse_colors(&data[..data.len() - 2]), None);
+        assert_eq!(parse_colors(b"garbage"), None);
+    }
+}
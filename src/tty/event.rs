@@ -1,4 +1,4 @@
-use crate::{Key, KeyMods};
+use crate::{Key, KeyMods, MediaKey};
 use crossterm::event;
 
 impl TryFrom<event::KeyEvent> for crate::KeyTyped {
@@ -9,16 +9,18 @@ impl TryFrom<event::KeyEvent> for crate::KeyTyped {
             code,
             modifiers,
             kind,
-            ..
+            state,
         }: crossterm::event::KeyEvent,
     ) -> Result<Self, Self::Error> {
-        let key = Key::try_from(code)?;
-        let mut mods = KeyMods {
-            shift: modifiers.intersects(event::KeyModifiers::SHIFT),
-            ctrl: modifiers.intersects(event::KeyModifiers::CONTROL),
-            alt: modifiers.intersects(event::KeyModifiers::ALT),
-            logo: modifiers.intersects(event::KeyModifiers::SUPER),
-        };
+        let mut key = Key::try_from(code)?;
+        // Terminals with keyboard enhancement can tell keypad keys apart.
+        if state.contains(event::KeyEventState::KEYPAD)
+            && let Key::Char(c) = key
+            && crate::event::KEYPAD_CHARS.contains(c)
+        {
+            key = Key::Keypad(c);
+        }
+        let mut mods = KeyMods::from(modifiers);
         if key.is_printable() {
             mods.shift = false;
         }
@@ -31,14 +33,27 @@ impl TryFrom<event::KeyEvent> for crate::KeyTyped {
     }
 }
 
+impl From<event::KeyModifiers> for KeyMods {
+    fn from(modifiers: event::KeyModifiers) -> Self {
+        KeyMods {
+            shift: modifiers.intersects(event::KeyModifiers::SHIFT),
+            ctrl: modifiers.intersects(event::KeyModifiers::CONTROL),
+            alt: modifiers.intersects(event::KeyModifiers::ALT),
+            logo: modifiers.intersects(event::KeyModifiers::SUPER),
+        }
+    }
+}
+
 impl TryFrom<crossterm::event::KeyCode> for crate::Key {
     type Error = ();
 
     fn try_from(
         key_code: crossterm::event::KeyCode,
     ) -> Result<Self, Self::Error> {
-        use crossterm::event::{KeyCode as K, ModifierKeyCode as M};
         use Key::*;
+        use crossterm::event::{
+            KeyCode as K, MediaKeyCode as Md, ModifierKeyCode as M,
+        };
 
         match key_code {
             K::Backspace => Ok(Backspace),
@@ -58,14 +73,29 @@ impl TryFrom<crossterm::event::KeyCode> for crate::Key {
             K::Char(c) => Ok(Char(c)),
             K::Null => Err(()),
             K::Esc => Ok(Esc),
-            K::CapsLock => Err(()),
-            K::NumLock => Err(()),
-            K::ScrollLock => Err(()),
-            K::PrintScreen => Err(()),
-            K::Pause => Err(()),
-            K::Menu => Err(()),
-            K::KeypadBegin => Err(()),
-            K::Media(_) => Err(()),
+            K::CapsLock => Ok(CapsLock),
+            K::NumLock => Ok(NumLock),
+            K::ScrollLock => Ok(ScrollLock),
+            K::PrintScreen => Ok(PrintScreen),
+            K::Pause => Ok(Pause),
+            K::Menu => Ok(Menu),
+            // Middle keypad key with num lock off.
+            K::KeypadBegin => Ok(Keypad('5')),
+            K::Media(m) => Ok(Media(match m {
+                Md::Play => MediaKey::Play,
+                Md::Pause => MediaKey::Pause,
+                Md::PlayPause => MediaKey::PlayPause,
+                Md::Reverse => MediaKey::Reverse,
+                Md::Stop => MediaKey::Stop,
+                Md::FastForward => MediaKey::FastForward,
+                Md::Rewind => MediaKey::Rewind,
+                Md::TrackNext => MediaKey::Next,
+                Md::TrackPrevious => MediaKey::Previous,
+                Md::Record => MediaKey::Record,
+                Md::LowerVolume => MediaKey::VolumeDown,
+                Md::RaiseVolume => MediaKey::VolumeUp,
+                Md::MuteVolume => MediaKey::Mute,
+            })),
             K::Modifier(M::LeftShift | M::RightShift) => Ok(Shift),
             K::Modifier(M::LeftControl | M::RightControl) => Ok(Ctrl),
             K::Modifier(M::LeftAlt | M::RightAlt) => Ok(Alt),
@@ -84,3 +114,78 @@ impl From<crossterm::event::MouseButton> for crate::MouseButton {
         }
     }
 }
+
+/// Turn raw terminal input into key events, for input that was read
+/// outside the crossterm event reader.
+///
+/// Understands printable and control characters, Alt chords and the common
+/// cursor key escape sequences, other escape sequences are dropped.
+pub fn decode_keys(input: &[u8]) -> Vec<event::KeyEvent> {
+    use event::{KeyCode as K, KeyModifiers as M};
+
+    let text = String::from_utf8_lossy(input);
+    let mut chars = text.chars();
+    let mut ret = Vec::new();
+
+    while let Some(c) = chars.next() {
+        let (code, mods) = match c {
+            '\x1b' => match chars.next() {
+                None => (K::Esc, M::NONE),
+                Some('[' | 'O') => {
+                    let mut param = String::new();
+                    let end = loop {
+                        match chars.next() {
+                            // Parameter and intermediate bytes.
+                            Some(c @ ' '..='?') => param.push(c),
+                            end => break end,
+                        }
+                    };
+                    match (end, param.as_str()) {
+                        (Some('A'), _) => (K::Up, M::NONE),
+                        (Some('B'), _) => (K::Down, M::NONE),
+                        (Some('C'), _) => (K::Right, M::NONE),
+                        (Some('D'), _) => (K::Left, M::NONE),
+                        (Some('H'), _) | (Some('~'), "1" | "7") => {
+                            (K::Home, M::NONE)
+                        }
+                        (Some('F'), _) | (Some('~'), "4" | "8") => {
+                            (K::End, M::NONE)
+                        }
+                        (Some('~'), "2") => (K::Insert, M::NONE),
+                        (Some('~'), "3") => (K::Delete, M::NONE),
+                        (Some('~'), "5") => (K::PageUp, M::NONE),
+                        (Some('~'), "6") => (K::PageDown, M::NONE),
+                        _ => continue,
+                    }
+                }
+                Some(c) => (K::Char(c), M::ALT),
+            },
+            '\r' | '\n' => (K::Enter, M::NONE),
+            '\t' => (K::Tab, M::NONE),
+            '\x7f' | '\x08' => (K::Backspace, M::NONE),
+            '\x01'..='\x1a' => {
+                ((K::Char((c as u8 - 1 + b'a') as char)), M::CONTROL)
+            }
+            c if c.is_control() => continue,
+            c => (K::Char(c), M::NONE),
+        };
+        ret.push(event::KeyEvent::new(code, mods));
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_raw_input() {
+        let keys: Vec<String> =
+            decode_keys(b"a\x1b[A\x1b[5~\x03\x1bx\r\x1b[?1u")
+                .into_iter()
+                .map(|k| crate::KeyTyped::try_from(k).unwrap().to_string())
+                .collect();
+        assert_eq!(keys, ["a", "Up", "PgUp", "C-c", "A-x", "Ret"]);
+    }
+}
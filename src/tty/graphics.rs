@@ -0,0 +1,461 @@
+//! Bitmap output through terminal graphics protocols.
+use std::fmt::Write;
+
+use base64::{Engine, engine::general_purpose};
+
+use super::{
+    color_depth::ColorDepth,
+    term_cell::{TermCell, TermColor},
+};
+use crate::{PixelMode, Rgba, X256Color};
+
+/// Method used to show pixel buffers on the terminal.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Protocol {
+    /// Fake pixels with block or Braille characters, works everywhere.
+    Chars,
+    /// DEC sixel graphics.
+    Sixel,
+    /// Kitty terminal graphics protocol.
+    Kitty,
+}
+
+impl Protocol {
+    /// Guess the best supported protocol from the environment.
+    ///
+    /// The `NAVNI_GRAPHICS` environment variable (`sixel`, `kitty` or
+    /// `chars` for character graphics) overrides the guess.
+    pub fn detect() -> Self {
+        Self::detect_from(|name| std::env::var(name).ok())
+    }
+
+    fn detect_from(var: impl Fn(&str) -> Option<String>) -> Self {
+        if let Some(value) = var("NAVNI_GRAPHICS") {
+            match value.to_lowercase().as_str() {
+                "chars" => return Protocol::Chars,
+                "sixel" => return Protocol::Sixel,
+                "kitty" => return Protocol::Kitty,
+                _ => log::warn!("Unknown NAVNI_GRAPHICS value {value:?}"),
+            }
+        }
+
+        // Terminal multiplexers don't pass the graphics through, even if
+        // the outer terminal's variables are visible.
+        if var("TMUX").is_some() || var("STY").is_some() {
+            return Protocol::Chars;
+        }
+
+        let term = var("TERM").unwrap_or_default();
+        let program = var("TERM_PROGRAM").unwrap_or_default();
+
+        if var("KITTY_WINDOW_ID").is_some()
+            || term == "xterm-kitty"
+            || term == "xterm-ghostty"
+            || program == "ghostty"
+            || program == "WezTerm"
+        {
+            Protocol::Kitty
+        } else if term.starts_with("foot")
+            || term.starts_with("mlterm")
+            || term.contains("sixel")
+            || program == "iTerm.app"
+            || program == "contour"
+        {
+            Protocol::Sixel
+        } else {
+            Protocol::Chars
+        }
+    }
+}
+
+/// Convert a pixel buffer into char cells that each show several pixels.
+///
+/// Every cell picks the two colors that best represent its pixels, so
+/// cells with more than two colors lose detail. Returns the dimensions of
+/// the cell buffer and the buffer. Colors are reduced to what the color
+/// depth allows.
+pub fn pseudo_pixels(
+    mode: PixelMode,
+    depth: ColorDepth,
+    w: u32,
+    h: u32,
+    buffer: &[Rgba],
+) -> (u32, u32, Vec<TermCell>) {
+    assert!(buffer.len() == (w * h) as usize);
+
+    let (mx, my) = mode.cell_pixels();
+    let (cw, ch) = (w.div_ceil(mx), h.div_ceil(my));
+
+    let cell = |c, fore, back| {
+        TermCell::new(
+            c,
+            TermColor::new(fore, depth),
+            TermColor::new(back, depth),
+        )
+    };
+
+    let mut pixels = Vec::with_capacity((mx * my) as usize);
+    let cells = (0..cw * ch)
+        .map(|i| {
+            let (x0, y0) = (i % cw * mx, i / cw * my);
+
+            // Pixels of the cell in row-major order, `None` for pixels
+            // outside the buffer.
+            pixels.clear();
+            pixels.extend((0..mx * my).map(|j| {
+                let (x, y) = (x0 + j % mx, y0 + j / mx);
+                (x < w && y < h).then(|| buffer[(x + y * w) as usize])
+            }));
+
+            match mode {
+                PixelMode::HalfBlock => {
+                    let top = pixels[0].unwrap_or_default();
+                    let bottom = pixels[1].unwrap_or_default();
+                    cell('▀', top, bottom)
+                }
+                PixelMode::Quadrant => {
+                    let (bits, fore, back) = split_colors(&pixels);
+                    cell(QUADRANTS[bits as usize], fore, back)
+                }
+                PixelMode::Braille => {
+                    let (bits, fore, back) = split_colors(&pixels);
+                    // Braille dot numbering goes down the left column
+                    // first, the bottom row dots were added last.
+                    let dots = [0, 3, 1, 4, 2, 5, 6, 7]
+                        .iter()
+                        .enumerate()
+                        .fold(0, |acc, (j, &d)| acc | (((bits >> j) & 1) << d));
+                    let c = char::from_u32(0x2800 + dots).unwrap();
+                    cell(c, fore, back)
+                }
+            }
+        })
+        .collect();
+
+    (cw, ch, cells)
+}
+
+/// Quadrant block chars indexed by bits for top left, top right, bottom
+/// left and bottom right quadrants.
+const QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟',
+    '█',
+];
+
+/// Split pixels into foreground and background groups.
+///
+/// Returns a bit mask of the foreground pixels and the average colors of
+/// the two groups. The foreground group is the one with fewer pixels.
+fn split_colors(pixels: &[Option<Rgba>]) -> (u32, Rgba, Rgba) {
+    let colors = pixels.iter().flatten().copied().collect::<Vec<_>>();
+    let Some(&first) = colors.first() else {
+        return (0, Rgba::default(), Rgba::default());
+    };
+
+    // Seed the groups with the two most different colors.
+    let (mut fore, mut back) = (first, first);
+    let mut max_dist = 0.0;
+    for (i, a) in colors.iter().enumerate() {
+        for b in &colors[i + 1..] {
+            let dist = a.square_dist(b);
+            if dist > max_dist {
+                (fore, back, max_dist) = (*a, *b, dist);
+            }
+        }
+    }
+
+    let mut bits = 0;
+    let (mut fore_sum, mut back_sum) = ([0; 4], [0; 4]);
+    let (mut fore_n, mut back_n) = (0, 0);
+    for (i, c) in pixels.iter().enumerate() {
+        let Some(c) = c else { continue };
+        let (sum, n) = if c.square_dist(&fore) < c.square_dist(&back) {
+            bits |= 1 << i;
+            (&mut fore_sum, &mut fore_n)
+        } else {
+            (&mut back_sum, &mut back_n)
+        };
+        for (s, x) in sum.iter_mut().zip([c.r, c.g, c.b, c.a]) {
+            *s += x as u32;
+        }
+        *n += 1;
+    }
+
+    let avg = |sum: [u32; 4], n: u32, default: Rgba| {
+        if n == 0 {
+            default
+        } else {
+            let [r, g, b, a] = sum.map(|s| ((s + n / 2) / n) as u8);
+            Rgba::new(r, g, b, a)
+        }
+    };
+
+    let (fore, back) =
+        (avg(fore_sum, fore_n, fore), avg(back_sum, back_n, back));
+
+    // Draw the smaller group as the foreground shape.
+    if fore_n > back_n {
+        let present = pixels
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.is_some())
+            .fold(0, |acc, (i, _)| acc | (1 << i));
+        (!bits & present, back, fore)
+    } else {
+        (bits, fore, back)
+    }
+}
+
+/// Magnify a pixel buffer by an integer factor.
+pub fn scale(w: u32, h: u32, buffer: &[Rgba], s: u32) -> Vec<Rgba> {
+    if s == 1 {
+        return buffer.to_vec();
+    }
+
+    let mut ret = Vec::with_capacity((w * h * s * s) as usize);
+    for y in 0..h * s {
+        let row = &buffer[((y / s) * w) as usize..][..w as usize];
+        for &c in row {
+            ret.extend(std::iter::repeat_n(c, s as usize));
+        }
+    }
+    ret
+}
+
+/// Encode a pixel buffer as a sixel image.
+///
+/// Colors are reduced to the xterm 256 color palette.
+pub fn sixel(w: u32, h: u32, buffer: &[Rgba]) -> String {
+    assert!(buffer.len() == (w * h) as usize);
+
+    let indices: Vec<u8> =
+        buffer.iter().map(|&c| X256Color::from(c).0).collect();
+
+    let mut used = [false; 256];
+    for &i in &indices {
+        used[i as usize] = true;
+    }
+
+    let mut ret = format!("\x1bP0;1;0q\"1;1;{w};{h}");
+
+    // Color registers, RGB components in percent.
+    for (i, _) in used.iter().enumerate().filter(|(_, a)| **a) {
+        let c = Rgba::from(X256Color(i as u8));
+        let p = |x: u8| (x as u32 * 100 + 127) / 255;
+        write!(ret, "#{i};2;{};{};{}", p(c.r), p(c.g), p(c.b)).unwrap();
+    }
+
+    let mut line = Vec::with_capacity(w as usize);
+    for y0 in (0..h).step_by(6) {
+        let band = (y0..(y0 + 6).min(h))
+            .map(|y| &indices[(y * w) as usize..][..w as usize])
+            .collect::<Vec<_>>();
+
+        let mut in_band = [false; 256];
+        for row in &band {
+            for &i in *row {
+                in_band[i as usize] = true;
+            }
+        }
+
+        for (color, _) in in_band.iter().enumerate().filter(|(_, a)| **a) {
+            line.clear();
+            line.extend((0..w as usize).map(|x| {
+                let bits = band
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, row)| row[x] as usize == color)
+                    .fold(0, |acc, (k, _)| acc | (1 << k));
+                (63 + bits) as u8 as char
+            }));
+
+            // Empty sixels at the end of the line can be left out.
+            while line.last() == Some(&'?') {
+                line.pop();
+            }
+
+            write!(ret, "#{color}").unwrap();
+            run_length_encode(&mut ret, &line);
+            ret.push('$');
+        }
+        ret.push('-');
+    }
+
+    ret.push_str("\x1b\\");
+    ret
+}
+
+fn run_length_encode(out: &mut String, line: &[char]) {
+    let mut i = 0;
+    while i < line.len() {
+        let c = line[i];
+        let n = line[i..].iter().take_while(|&&a| a == c).count();
+        if n > 3 {
+            write!(out, "!{n}{c}").unwrap();
+        } else {
+            out.extend(std::iter::repeat_n(c, n));
+        }
+        i += n;
+    }
+}
+
+/// Encode a pixel buffer as a kitty graphics protocol image.
+///
+/// The image replaces any previous image sent with this function and
+/// leaves the cursor in place.
+pub fn kitty(w: u32, h: u32, buffer: &[Rgba]) -> String {
+    // Maximum payload size for a single escape sequence.
+    const CHUNK: usize = 4096;
+
+    assert!(buffer.len() == (w * h) as usize);
+
+    let rgb: Vec<u8> = buffer.iter().flat_map(|c| [c.r, c.g, c.b]).collect();
+    let data = general_purpose::STANDARD.encode(rgb);
+
+    let mut ret = String::with_capacity(data.len() + data.len() / CHUNK * 16);
+    let chunks = data.as_bytes().chunks(CHUNK).collect::<Vec<_>>();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        // Chunks are slices of base64 text, so they're valid UTF-8.
+        let chunk = std::str::from_utf8(chunk).unwrap();
+        if i == 0 {
+            write!(
+                ret,
+                "\x1b_Ga=T,f=24,s={w},v={h},i=1,p=1,q=2,C=1,m={more};{chunk}\x1b\\"
+            )
+            .unwrap();
+        } else {
+            write!(ret, "\x1b_Gm={more};{chunk}\x1b\\").unwrap();
+        }
+    }
+    ret
+}
+
+/// Escape sequence that removes all kitty graphics images from screen.
+pub const KITTY_CLEAR: &str = "\x1b_Ga=d,q=2\x1b\\";
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn env<'a>(
+        vars: &'a [(&str, &str)],
+    ) -> impl Fn(&str) -> Option<String> + 'a {
+        |name| {
+            vars.iter()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| v.to_string())
+        }
+    }
+
+    #[test]
+    fn detect() {
+        use Protocol::*;
+
+        assert_eq!(Protocol::detect_from(env(&[])), Chars);
+        assert_eq!(
+            Protocol::detect_from(env(&[("TERM", "xterm-kitty")])),
+            Kitty
+        );
+        assert_eq!(Protocol::detect_from(env(&[("TERM", "foot")])), Sixel);
+        assert_eq!(
+            Protocol::detect_from(env(&[
+                ("TERM", "xterm-kitty"),
+                ("TMUX", "/tmp/tmux")
+            ])),
+            Chars
+        );
+        assert_eq!(
+            Protocol::detect_from(env(&[
+                ("TERM", "xterm-256color"),
+                ("NAVNI_GRAPHICS", "Sixel")
+            ])),
+            Sixel
+        );
+    }
+
+    #[test]
+    fn quadrant_and_braille() {
+        let (r, b) = (Rgba::RED, Rgba::BLUE);
+        let cell = |c, fore, back| {
+            TermCell::new(c, TermColor::Rgb(fore), TermColor::Rgb(back))
+        };
+        #[rustfmt::skip]
+        let buf = [
+            r, b,
+            b, r,
+            b, b,
+            b, r,
+        ];
+
+        let (w, h, cells) = pseudo_pixels(
+            PixelMode::Quadrant,
+            ColorDepth::TrueColor,
+            2,
+            4,
+            &buf,
+        );
+        assert_eq!((w, h), (1, 2));
+        assert_eq!(cells[0], cell('▚', r, b));
+        assert_eq!(cells[1], cell('▗', r, b));
+
+        let (w, h, cells) = pseudo_pixels(
+            PixelMode::Braille,
+            ColorDepth::TrueColor,
+            2,
+            4,
+            &buf,
+        );
+        assert_eq!((w, h), (1, 1));
+        // Dots 1, 5 and 8.
+        assert_eq!(cells[0], cell('\u{2891}', r, b));
+
+        // Uneven sizes get padded.
+        let (w, h, _) = pseudo_pixels(
+            PixelMode::Braille,
+            ColorDepth::TrueColor,
+            3,
+            5,
+            &[r; 15],
+        );
+        assert_eq!((w, h), (2, 2));
+    }
+
+    #[test]
+    fn scaling() {
+        let buf = [Rgba::RED, Rgba::BLUE];
+        assert_eq!(
+            scale(2, 1, &buf, 2),
+            [Rgba::RED, Rgba::RED, Rgba::BLUE, Rgba::BLUE].repeat(2)
+        );
+    }
+
+    #[test]
+    fn sixel_encoding() {
+        // Single column of 7 pixels, six in the first band and one in the
+        // second.
+        let buf = vec![Rgba::new(0xff, 0, 0, 0xff); 7];
+        let red = X256Color::from(buf[0]).0;
+        assert_eq!(
+            sixel(1, 7, &buf),
+            format!(
+                "\x1bP0;1;0q\"1;1;1;7#{red};2;100;0;0#{red}~$-#{red}@$-\x1b\\"
+            )
+        );
+
+        let mut line = String::new();
+        run_length_encode(&mut line, &['~', '~', '~', '~', '~', '@']);
+        assert_eq!(line, "!5~@");
+    }
+
+    #[test]
+    fn kitty_chunks() {
+        let buf = vec![Rgba::BLACK; 4096];
+        let seq = kitty(64, 64, &buf);
+        assert!(seq.starts_with("\x1b_Ga=T,f=24,s=64,v=64,"));
+        // 12 KiB of RGB data is 16 KiB of base64, four chunks.
+        assert_eq!(seq.matches("\x1b_G").count(), 4);
+        assert_eq!(seq.matches("m=1;").count(), 3);
+        assert_eq!(seq.matches("m=0;").count(), 1);
+    }
+}
@@ -0,0 +1,315 @@
+//! Terminal palette control with OSC 4, 10 and 11 escape sequences.
+//!
+//! The terminal's original colors are queried before the first change so
+//! they can be put back on exit. Terminals that don't answer the queries are
+//! assumed to not support changing the palette either and are left alone.
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::Write as _,
+    os::fd::AsRawFd,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use crate::{Rgba, X256Color};
+
+/// Escape sequence that restores the original palette, set once the
+/// original palette has been queried.
+static RESTORE: OnceLock<String> = OnceLock::new();
+
+/// How long to wait for the terminal to answer the palette query.
+const QUERY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Original terminal colors.
+#[derive(Clone, Default, Debug, PartialEq)]
+struct Saved {
+    colors: [Option<Rgba>; 16],
+    foreground: Option<Rgba>,
+    background: Option<Rgba>,
+}
+
+impl Saved {
+    fn is_empty(&self) -> bool {
+        self.colors.iter().all(|c| c.is_none())
+            && self.foreground.is_none()
+            && self.background.is_none()
+    }
+
+    /// Escape sequence that sets the saved colors back and resets the
+    /// unknown ones to terminal defaults.
+    fn restore_sequence(&self) -> String {
+        let mut ret = String::new();
+        for (i, c) in self.colors.iter().enumerate() {
+            match c {
+                Some(c) => write!(ret, "\x1b]4;{i};{}\x1b\\", spec(*c)),
+                None => write!(ret, "\x1b]104;{i}\x1b\\"),
+            }
+            .unwrap();
+        }
+        match self.foreground {
+            Some(c) => write!(ret, "\x1b]10;{}\x1b\\", spec(c)),
+            None => write!(ret, "\x1b]110\x1b\\"),
+        }
+        .unwrap();
+        match self.background {
+            Some(c) => write!(ret, "\x1b]11;{}\x1b\\", spec(c)),
+            None => write!(ret, "\x1b]111\x1b\\"),
+        }
+        .unwrap();
+        ret
+    }
+}
+
+/// Set the terminal's system colors and default colors.
+///
+/// Returns any user input that was read from the terminal while waiting for
+/// the answer to the palette query.
+pub fn set(palette: &[Rgba; 16]) -> Vec<u8> {
+    let mut input = Vec::new();
+    let restore = RESTORE.get_or_init(|| {
+        let (saved, rest) = query();
+        input = rest;
+        match saved {
+            Some(saved) if !saved.is_empty() => saved.restore_sequence(),
+            _ => {
+                log::info!("Terminal did not answer palette query");
+                String::new()
+            }
+        }
+    });
+    if restore.is_empty() {
+        return input;
+    }
+
+    let mut stdout = std::io::stdout();
+    stdout.write_all(set_sequence(palette).as_bytes()).unwrap();
+    stdout.flush().unwrap();
+    input
+}
+
+/// Restore the palette from before the first `set` call.
+pub fn restore(out: &mut impl std::io::Write) {
+    if let Some(seq) = RESTORE.get() {
+        let _ = out.write_all(seq.as_bytes());
+    }
+}
+
+fn set_sequence(palette: &[Rgba; 16]) -> String {
+    let mut ret = String::new();
+    for (i, c) in palette.iter().enumerate() {
+        write!(ret, "\x1b]4;{i};{}\x1b\\", spec(*c)).unwrap();
+    }
+    // Cells with the default colors are drawn without color attributes, so
+    // the terminal defaults must match the corresponding system colors.
+    let fore = palette[X256Color::FOREGROUND.0 as usize];
+    let back = palette[X256Color::BACKGROUND.0 as usize];
+    write!(ret, "\x1b]10;{}\x1b\\", spec(fore)).unwrap();
+    write!(ret, "\x1b]11;{}\x1b\\", spec(back)).unwrap();
+    ret
+}
+
+/// X11 color spec for a color.
+fn spec(c: Rgba) -> String {
+    format!("rgb:{:02x}/{:02x}/{:02x}", c.r, c.g, c.b)
+}
+
+/// Ask the terminal for its current colors.
+///
+/// The queries are followed by a primary device attributes request that
+/// practically all terminals answer, so there's no need to wait for the
+/// full timeout on terminals that ignore the palette queries.
+///
+/// Also returns the input that was read but wasn't part of the answers.
+fn query() -> (Option<Saved>, Vec<u8>) {
+    let mut seq = String::new();
+    for i in 0..16 {
+        write!(seq, "\x1b]4;{i};?\x1b\\").unwrap();
+    }
+    seq.push_str("\x1b]10;?\x1b\\\x1b]11;?\x1b\\\x1b[c");
+
+    let mut stdout = std::io::stdout();
+    if stdout.write_all(seq.as_bytes()).is_err() || stdout.flush().is_err() {
+        return (None, Vec::new());
+    }
+
+    let (response, answered) = read_response(QUERY_TIMEOUT);
+    let (answers, rest) = split_answers(&response);
+    (answered.then(|| parse_response(&answers)), rest)
+}
+
+/// Read the terminal until a device attributes response or until timeout.
+///
+/// Returns everything that was read and whether the response was seen.
+fn read_response(timeout: Duration) -> (Vec<u8>, bool) {
+    let start = Instant::now();
+    let mut ret = Vec::new();
+    let mut buf = [0u8; 1024];
+
+    // Read the same terminal device the crossterm event reader reads, which
+    // is `/dev/tty` if stdin has been redirected.
+    //
+    // SAFETY: Checking a valid file descriptor.
+    let tty = if unsafe { libc::isatty(libc::STDIN_FILENO) } == 1 {
+        None
+    } else {
+        match File::open("/dev/tty") {
+            Ok(file) => Some(file),
+            Err(_) => return (ret, false),
+        }
+    };
+    let fd = tty.as_ref().map_or(libc::STDIN_FILENO, |f| f.as_raw_fd());
+
+    while !is_answered(&ret) {
+        let Some(left) = timeout.checked_sub(start.elapsed()) else {
+            return (ret, false);
+        };
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: Polling a single valid pollfd struct.
+        let ready =
+            unsafe { libc::poll(&mut pollfd, 1, left.as_millis() as _) };
+        if ready <= 0 {
+            return (ret, false);
+        }
+
+        // Read the file descriptor directly instead of through
+        // `std::io::stdin`, which would buffer input the terminal event
+        // reader needs to see.
+        //
+        // SAFETY: Reading into a buffer of the given length.
+        let n = unsafe { libc::read(fd, buf.as_mut_ptr() as _, buf.len()) };
+        if n <= 0 {
+            return (ret, false);
+        }
+        ret.extend_from_slice(&buf[..n as usize]);
+    }
+
+    (ret, true)
+}
+
+/// Separate OSC and device attributes answers from other terminal input.
+fn split_answers(input: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let (mut answers, mut rest) = (Vec::new(), Vec::new());
+    let mut i = 0;
+    while i < input.len() {
+        let tail = &input[i..];
+        let len = if tail.starts_with(b"\x1b]") {
+            // OSC answer, terminated by BEL or ESC \.
+            let bel = tail.iter().position(|&b| b == b'\x07').map(|p| p + 1);
+            let st =
+                tail.windows(2).position(|a| a == b"\x1b\\").map(|p| p + 2);
+            bel.into_iter().chain(st).min().unwrap_or(tail.len())
+        } else if tail.starts_with(b"\x1b[?")
+            && let Some(p) = tail[3..]
+                .iter()
+                .position(|&b| !(b.is_ascii_digit() || b == b';'))
+            && tail[3 + p] == b'c'
+        {
+            // Device attributes answer.
+            3 + p + 1
+        } else {
+            rest.push(input[i]);
+            i += 1;
+            continue;
+        };
+        answers.extend_from_slice(&tail[..len]);
+        i += len;
+    }
+    (answers, rest)
+}
+
+/// Check for the `ESC [ ? ... c` device attributes response.
+fn is_answered(response: &[u8]) -> bool {
+    let Some(pos) = response.windows(3).position(|a| a == b"\x1b[?") else {
+        return false;
+    };
+    response[pos..].contains(&b'c')
+}
+
+fn parse_response(response: &[u8]) -> Saved {
+    let mut ret = Saved::default();
+    let text = String::from_utf8_lossy(response);
+
+    // Answers look like `ESC ] 4 ; 1 ; rgb:aaaa/0000/0000 ST`, where ST is
+    // either `ESC \` or BEL.
+    for answer in text.split("\x1b]").skip(1) {
+        let answer = answer.split(['\x1b', '\x07']).next().unwrap_or("");
+        let mut parts = answer.split(';');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some("4"), Some(i), Some(color)) => {
+                if let (Ok(i), Some(color)) = (i.parse::<usize>(), parse(color))
+                    && i < 16
+                {
+                    ret.colors[i] = Some(color);
+                }
+            }
+            (Some("10"), Some(color), None) => ret.foreground = parse(color),
+            (Some("11"), Some(color), None) => ret.background = parse(color),
+            _ => {}
+        }
+    }
+
+    ret
+}
+
+/// Parse an `rgb:r/g/b` color spec with 1 to 4 hex digits per component.
+fn parse(spec: &str) -> Option<Rgba> {
+    let spec = spec.strip_prefix("rgb:")?;
+    let mut ret = [0; 3];
+    let mut parts = spec.split('/');
+    for c in ret.iter_mut() {
+        let part = parts.next()?;
+        if part.is_empty() || part.len() > 4 {
+            return None;
+        }
+        let max = (1u32 << (4 * part.len())) - 1;
+        let value = u32::from_str_radix(part, 16).ok()?;
+        *c = ((value * 255 + max / 2) / max) as u8;
+    }
+    Some(Rgba::new(ret[0], ret[1], ret[2], 0xff))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_answers() {
+        let response = b"\x1b]4;1;rgb:aaaa/0000/0000\x1b\\\
+            \x1b]4;15;rgb:ff/ff/ff\x07\
+            \x1b]11;rgb:1010/2020/3030\x1b\\\
+            \x1b[?62;22c";
+        assert!(is_answered(response));
+
+        let saved = parse_response(response);
+        assert_eq!(saved.colors[1], Some(Rgba::new(0xaa, 0, 0, 0xff)));
+        assert_eq!(saved.colors[15], Some(Rgba::WHITE));
+        assert_eq!(saved.colors[0], None);
+        assert_eq!(saved.foreground, None);
+        assert_eq!(saved.background, Some(Rgba::new(0x10, 0x20, 0x30, 0xff)));
+
+        let seq = saved.restore_sequence();
+        assert!(seq.starts_with("\x1b]104;0\x1b\\\x1b]4;1;rgb:aa/00/00\x1b\\"));
+        assert!(seq.contains("\x1b]110\x1b\\"));
+
+        assert!(!is_answered(b"\x1b]4;1;rgb:aaaa/0000/0000\x1b\\"));
+        assert!(parse_response(b"\x1b[?62c").is_empty());
+    }
+
+    #[test]
+    fn keep_user_input() {
+        let (answers, rest) = split_answers(
+            b"ab\x1b]4;1;rgb:aa/00/00\x07c\x1b]11;rgb:10/20/30\x1b\\\
+            \x1b[A\x1b[?62;22cd",
+        );
+        assert_eq!(
+            answers,
+            b"\x1b]4;1;rgb:aa/00/00\x07\x1b]11;rgb:10/20/30\x1b\\\x1b[?62;22c"
+        );
+        assert_eq!(rest, b"abc\x1b[Ad");
+    }
+}
@@ -1,21 +1,35 @@
 use std::{
     collections::VecDeque,
-    future::Future,
     io::Write,
-    pin::Pin,
-    sync::{Mutex, OnceLock},
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
     time::Duration,
 };
 
+use base64::{Engine, engine::general_purpose};
 use crossterm::{cursor, event, queue, style, terminal};
 use rustc_hash::FxHashSet as HashSet;
 use signal_hook::{consts::SIGTERM, iterator::Signals};
 
-use crate::{CharCell, Key, KeyTyped, MouseState, Rgba, X256Color};
+use super::{
+    color_depth::ColorDepth,
+    graphics::{self, Protocol},
+    term_cell::{TermCell, TermColor},
+};
+use crate::{
+    BackgroundPolicy, CharCell, Event, Key, KeyTyped, MouseState, PixelMode,
+    Rgba, TimedEvent, TtyConfig,
+};
 
 pub static RUNTIME: OnceLock<Mutex<Runtime>> = OnceLock::new();
 
-pub static mut FUTURE: Option<Pin<Box<dyn Future<Output = ()>>>> = None;
+// Terminal modes that are currently on and must be undone by `cleanup`.
+// Kept outside the runtime so the signal and panic handlers can read them.
+static ALTERNATE_SCREEN: AtomicBool = AtomicBool::new(false);
+static KEYBOARD_ENHANCEMENT: AtomicBool = AtomicBool::new(false);
+static MOUSE_CAPTURE: AtomicBool = AtomicBool::new(false);
 
 pub fn with<F, T>(mut f: F) -> T
 where
@@ -31,7 +45,7 @@ where
 
 pub struct Runtime {
     pub(crate) keypress: VecDeque<KeyTyped>,
-    prev_buffer: (u32, u32, Vec<CharCell>),
+    prev_buffer: (u32, u32, Vec<TermCell>),
     size: (u32, u32),
     pub(crate) mouse_state: MouseState,
     // Store last frame's projection that needs to be applied to mouse
@@ -41,21 +55,48 @@ pub struct Runtime {
     //
     // Requires a terminal that supports progressive keyboard enhancement.
     release_detection: bool,
-    key_down: HashSet<Key>,
+    pub(crate) key_down: HashSet<Key>,
+    pub(crate) events: Vec<TimedEvent>,
+    // Last text pasted into or copied from the application.
+    pub(crate) clipboard: Option<String>,
 
+    // Set when focus is lost, cleared by any input. Blocking in the
+    // background waits only while this is set.
     focus_lost: bool,
+    // Whether the terminal has focus, as reported by focus change events.
+    pub(crate) focused: bool,
+    pub(crate) background_policy: BackgroundPolicy,
+    last_frame_time: f64,
+    // Whether terminal mouse reporting is on.
+    mouse_capture: bool,
+    pub(crate) click_interval: f64,
+
+    // How pixel buffers are shown.
+    graphics: Protocol,
+    // Character encoding for pixels when there's no bitmap graphics.
+    pixel_mode: PixelMode,
+    // Colors the terminal can show.
+    color_depth: ColorDepth,
+    // Size of a char cell in screen pixels, needed for bitmap graphics.
+    cell_size: (u32, u32),
+    // Size of the bitmap currently on screen, if any.
+    bitmap: Option<(u32, u32)>,
 }
 
+/// Map from terminal cell coordinates to buffer coordinates, `(pos -
+/// offset) * num / den`.
 struct MouseTransform {
     offset: [i32; 2],
-    scale: [i32; 2],
+    num: [i32; 2],
+    den: [i32; 2],
 }
 
 impl Default for MouseTransform {
     fn default() -> Self {
         MouseTransform {
             offset: [0, 0],
-            scale: [1, 1],
+            num: [1, 1],
+            den: [1, 1],
         }
     }
 }
@@ -67,22 +108,36 @@ impl Drop for Runtime {
 }
 
 impl Runtime {
-    pub fn new() -> Self {
+    pub fn new(config: &TtyConfig) -> Self {
         let mut stdout = std::io::stdout();
+        if config.mouse_capture {
+            queue!(stdout, event::EnableMouseCapture).unwrap();
+            MOUSE_CAPTURE.store(true, Ordering::SeqCst);
+        }
         queue!(
             stdout,
-            event::EnableMouseCapture,
             event::EnableFocusChange,
-            terminal::EnterAlternateScreen,
-            cursor::Hide,
-            event::PushKeyboardEnhancementFlags(
-                // Enable tracking of release events.
-                event::KeyboardEnhancementFlags::REPORT_EVENT_TYPES
-                | event::KeyboardEnhancementFlags::REPORT_ALTERNATE_KEYS
-                | event::KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
-            ),
+            event::EnableBracketedPaste
         )
         .unwrap();
+        if config.alternate_screen {
+            queue!(stdout, terminal::EnterAlternateScreen).unwrap();
+            ALTERNATE_SCREEN.store(true, Ordering::SeqCst);
+        }
+        queue!(stdout, cursor::Hide).unwrap();
+        if config.keyboard_enhancement {
+            queue!(
+                stdout,
+                event::PushKeyboardEnhancementFlags(
+                    // Enable tracking of release events.
+                    event::KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                    | event::KeyboardEnhancementFlags::REPORT_ALTERNATE_KEYS
+                    | event::KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+                ),
+            )
+            .unwrap();
+            KEYBOARD_ENHANCEMENT.store(true, Ordering::SeqCst);
+        }
         terminal::enable_raw_mode().unwrap();
         stdout.flush().unwrap();
 
@@ -111,45 +166,141 @@ impl Runtime {
             (80, 24)
         };
 
+        let mut graphics = Protocol::detect();
+        let cell_size = cell_size().unwrap_or_default();
+        if graphics != Protocol::Chars && cell_size == (0, 0) {
+            log::info!(
+                "Terminal pixel size unknown, can't use {graphics:?} graphics"
+            );
+            graphics = Protocol::Chars;
+        }
+
         Runtime {
             keypress: Default::default(),
             prev_buffer: Default::default(),
             size,
             mouse_state: Default::default(),
             mouse_transform: Default::default(),
-            release_detection: terminal::supports_keyboard_enhancement()
-                .unwrap_or(false),
+            release_detection: config.keyboard_enhancement
+                && terminal::supports_keyboard_enhancement().unwrap_or(false),
             key_down: Default::default(),
+            events: Default::default(),
+            clipboard: None,
             focus_lost: false,
+            focused: true,
+            background_policy: BackgroundPolicy::Block,
+            last_frame_time: 0.0,
+            mouse_capture: config.mouse_capture,
+            click_interval: MouseState::DEFAULT_CLICK_INTERVAL,
+            graphics,
+            pixel_mode: Default::default(),
+            color_depth: ColorDepth::detect(),
+            cell_size,
+            bitmap: None,
         }
     }
 
     pub fn draw_pixels(&mut self, w: u32, h: u32, buffer: &[Rgba]) {
-        let cells: Vec<CharCell> = (0..w * h / 2)
-            .map(|i| {
-                let (x, y) = (i % w, (i / w) * 2);
-                CharCell::new(
-                    '▀',
-                    buffer[(x + y * w) as usize],
-                    buffer[(x + (y + 1) * w) as usize],
-                )
-            })
-            .collect();
-
-        self.draw_chars(w, h / 2, &cells);
-
-        // Set scaling to account for the fake y axis doubling. Do the setting
-        // here because draw_chars will also set inv_scale.
-        self.mouse_transform.scale = [1, 2];
+        assert!(buffer.len() == (w * h) as usize);
+
+        if self.graphics != Protocol::Chars {
+            self.draw_bitmap(w, h, buffer);
+            return;
+        }
+
+        let (cw, ch, cells) = graphics::pseudo_pixels(
+            self.pixel_mode,
+            self.color_depth,
+            w,
+            h,
+            buffer,
+        );
+        self.draw_cells(cw, ch, &cells);
+
+        // Set scaling to account for the multiple pixels per cell. Do the
+        // setting here because draw_cells will also set the transform.
+        let (mx, my) = self.pixel_mode.cell_pixels();
+        self.mouse_transform.num = [mx as i32, my as i32];
+    }
+
+    pub fn set_pixel_mode(&mut self, mode: PixelMode) {
+        self.pixel_mode = mode;
+    }
+
+    /// Draw pixels as an actual bitmap using a terminal graphics protocol.
+    fn draw_bitmap(&mut self, w: u32, h: u32, buffer: &[Rgba]) {
+        if w == 0 || h == 0 {
+            return;
+        }
+
+        let mut stdout = std::io::stdout();
+        let (cw, ch) = self.cell_size;
+
+        // Sixel images can scroll the screen if they reach the bottom line,
+        // so leave it empty.
+        let rows = if self.graphics == Protocol::Sixel {
+            self.size.1.saturating_sub(1)
+        } else {
+            self.size.1
+        };
+
+        // Magnify small buffers by the largest integer factor that fits.
+        let (area_w, area_h) = (self.size.0 * cw, rows * ch);
+        let mut s = 1;
+        while (s + 1) * w <= area_w && (s + 1) * h <= area_h {
+            s += 1;
+        }
+
+        let (x_offset, y_offset) = (
+            self.size.0.saturating_sub((w * s).div_ceil(cw)) / 2,
+            rows.saturating_sub((h * s).div_ceil(ch)) / 2,
+        );
+
+        self.mouse_transform = MouseTransform {
+            offset: [x_offset as i32, y_offset as i32],
+            num: [cw as i32, ch as i32],
+            den: [s as i32, s as i32],
+        };
+
+        if self.bitmap != Some((w, h)) {
+            if self.graphics == Protocol::Kitty {
+                stdout.write_all(graphics::KITTY_CLEAR.as_bytes()).unwrap();
+            }
+            queue!(stdout, terminal::Clear(terminal::ClearType::All)).unwrap();
+            self.bitmap = Some((w, h));
+        }
+        // Make the next draw_chars redraw everything.
+        self.prev_buffer = Default::default();
+
+        let buffer = graphics::scale(w, h, buffer, s);
+        let data = match self.graphics {
+            Protocol::Sixel => graphics::sixel(w * s, h * s, &buffer),
+            Protocol::Kitty => graphics::kitty(w * s, h * s, &buffer),
+            Protocol::Chars => unreachable!(),
+        };
+
+        queue!(stdout, cursor::MoveTo(x_offset as u16, y_offset as u16))
+            .unwrap();
+        stdout.write_all(data.as_bytes()).unwrap();
+        stdout.flush().unwrap();
     }
 
     pub fn draw_chars(&mut self, w: u32, h: u32, buffer: &[CharCell]) {
         assert!(buffer.len() == (w * h) as usize);
 
-        self.mouse_transform.scale = [1, 1];
+        let cells: Vec<TermCell> = buffer.iter().map(|&c| c.into()).collect();
+        self.draw_cells(w, h, &cells);
+    }
+
+    fn draw_cells(&mut self, w: u32, h: u32, buffer: &[TermCell]) {
+        self.mouse_transform = Default::default();
 
         let mut stdout = std::io::stdout();
 
+        if self.bitmap.take().is_some() && self.graphics == Protocol::Kitty {
+            stdout.write_all(graphics::KITTY_CLEAR.as_bytes()).unwrap();
+        }
+
         if self.prev_buffer.0 != w || self.prev_buffer.1 != h {
             // Clear the screen after a resize.
             queue!(stdout, terminal::Clear(terminal::ClearType::All),).unwrap();
@@ -171,10 +322,7 @@ impl Runtime {
         // Adjust mouse pos for the small buffer.
         self.mouse_transform.offset = [x_offset as i32, y_offset as i32];
 
-        let mut prev_cell = CharCell {
-            c: 0xffff,
-            ..Default::default()
-        };
+        let mut prev_cell: Option<TermCell> = None;
 
         let mut made_changes = false;
 
@@ -206,12 +354,14 @@ impl Runtime {
                     need_goto = false;
                 }
 
-                let cell = buffer[(x + y * w) as usize];
+                let cell =
+                    buffer[(x + y * w) as usize].downgrade(self.color_depth);
 
-                let color_changed = prev_cell.c == 0xffff
-                    || cell.foreground != prev_cell.foreground
-                    || cell.background != prev_cell.background;
-                prev_cell = cell;
+                let color_changed = prev_cell.is_none_or(|p| {
+                    cell.foreground != p.foreground
+                        || cell.background != p.background
+                });
+                prev_cell = Some(cell);
 
                 if color_changed {
                     // Determine terminal ops from color
@@ -219,8 +369,8 @@ impl Runtime {
                     //   inverse display.
                     // * System foreground as foregound marks no color.
                     // * System colors 8-15 are styled bold.
-                    let is_inverse = cell.foreground == X256Color::BACKGROUND
-                        && cell.background != X256Color::BACKGROUND;
+                    let is_inverse = cell.foreground == TermColor::BACKGROUND
+                        && cell.background != TermColor::BACKGROUND;
                     let foreground = if is_inverse {
                         cell.background
                     } else {
@@ -234,27 +384,25 @@ impl Runtime {
                             style::SetAttribute(style::Attribute::Reverse,)
                         )
                         .unwrap();
-                    } else if cell.background != X256Color::BACKGROUND {
-                        queue!(
+                    } else if cell.background != TermColor::BACKGROUND {
+                        write!(
                             stdout,
-                            style::SetBackgroundColor(style::Color::AnsiValue(
-                                cell.background.0
-                            ),)
+                            "\x1b[{}m",
+                            cell.background.sgr(true, self.color_depth)
                         )
                         .unwrap();
                     }
-                    if foreground != X256Color::FOREGROUND
-                        && foreground != X256Color::BOLD_FOREGROUND
+                    if foreground != TermColor::FOREGROUND
+                        && foreground != TermColor::BOLD_FOREGROUND
                     {
-                        queue!(
+                        write!(
                             stdout,
-                            style::SetForegroundColor(style::Color::AnsiValue(
-                                foreground.0
-                            ),)
+                            "\x1b[{}m",
+                            foreground.sgr(false, self.color_depth)
                         )
                         .unwrap();
                     }
-                    if foreground.0 >= 8 && foreground.0 < 16 && !is_inverse {
+                    if foreground.is_bold() && !is_inverse {
                         queue!(
                             stdout,
                             style::SetAttribute(style::Attribute::Bold,)
@@ -263,13 +411,7 @@ impl Runtime {
                     }
                 }
 
-                if let Some(c) = char::from_u32(cell.c as u32) {
-                    let c = match c {
-                        '\0' => ' ',
-                        c => c,
-                    };
-                    print!("{c}");
-                }
+                print!("{}", cell.c);
             }
         }
 
@@ -281,8 +423,20 @@ impl Runtime {
     }
 
     pub fn pixel_resolution(&self) -> (u32, u32) {
-        // Block pseudopixel size multipliers.
-        (self.size.0, self.size.1 * 2)
+        match self.graphics {
+            Protocol::Chars => {
+                let (mx, my) = self.pixel_mode.cell_pixels();
+                (self.size.0 * mx, self.size.1 * my)
+            }
+            Protocol::Sixel => (
+                self.size.0 * self.cell_size.0,
+                self.size.1.saturating_sub(1) * self.cell_size.1,
+            ),
+            Protocol::Kitty => (
+                self.size.0 * self.cell_size.0,
+                self.size.1 * self.cell_size.1,
+            ),
+        }
     }
 
     pub fn char_resolution(&self) -> (u32, u32) {
@@ -297,7 +451,11 @@ impl Runtime {
 
     fn resize(&mut self, w: u32, h: u32) {
         self.prev_buffer = Default::default();
+        self.bitmap = None;
         self.size = (w, h);
+        if let Some(cell_size) = cell_size() {
+            self.cell_size = cell_size;
+        }
     }
 
     pub fn process_event(&mut self, event: event::Event) {
@@ -307,6 +465,7 @@ impl Runtime {
             event::Event::Key(k) if k.kind == event::KeyEventKind::Release => {
                 if let Ok(k) = KeyTyped::try_from(k) {
                     self.key_down.remove(&k.key());
+                    self.push_event(Event::KeyUp(k));
                 }
             }
             event::Event::Key(k) => {
@@ -316,56 +475,86 @@ impl Runtime {
                     if self.release_detection {
                         self.key_down.insert(k.key());
                     }
+
+                    self.push_event(if k.is_repeat() {
+                        Event::KeyRepeat(k)
+                    } else {
+                        Event::KeyDown(k)
+                    });
+                    if let Some(c) = k.text() {
+                        self.push_event(Event::Text(c));
+                    }
                 }
             }
+            // Reports still in flight after capture was turned off.
+            event::Event::Mouse(_) if !self.mouse_capture => {}
             event::Event::Mouse(event::MouseEvent {
                 kind,
                 column,
                 row,
-                ..
+                modifiers,
             }) => {
-                let (x, y) = (column as i32, row as i32);
-                match kind {
+                let pos = self.transform_mouse_pos([column as _, row as _]);
+                let scroll = |u, v| Event::Scroll(pos, [u, v]);
+                let event = match kind {
                     event::MouseEventKind::Down(button) => {
-                        self.mouse_state.button_down(button.into());
+                        *self.mouse_state.cursor_pos_mut() = pos;
+                        self.mouse_state.button_down(
+                            button.into(),
+                            modifiers.into(),
+                            super::now(),
+                            self.click_interval,
+                        );
+                        Event::MouseDown(pos, button.into())
                     }
                     event::MouseEventKind::Up(button) => {
                         self.mouse_state.button_up(button.into());
+                        Event::MouseUp(pos, button.into())
                     }
-                    event::MouseEventKind::Drag(_) => {
-                        *self.mouse_state.cursor_pos_mut() =
-                            self.transform_mouse_pos([x, y]);
-                    }
-                    event::MouseEventKind::Moved => {
-                        *self.mouse_state.cursor_pos_mut() =
-                            self.transform_mouse_pos([x, y]);
+                    event::MouseEventKind::Drag(_)
+                    | event::MouseEventKind::Moved => {
+                        *self.mouse_state.cursor_pos_mut() = pos;
+                        Event::MouseMove(pos)
                     }
                     event::MouseEventKind::ScrollDown => {
                         self.mouse_state.scroll(0, 1);
+                        scroll(0, 1)
                     }
                     event::MouseEventKind::ScrollUp => {
                         self.mouse_state.scroll(0, -1);
+                        scroll(0, -1)
                     }
                     event::MouseEventKind::ScrollLeft => {
                         self.mouse_state.scroll(-1, 0);
+                        scroll(-1, 0)
                     }
                     event::MouseEventKind::ScrollRight => {
                         self.mouse_state.scroll(1, 0);
+                        scroll(1, 0)
                     }
-                }
+                };
+                self.push_event(event);
             }
             event::Event::Resize(w, h) => {
                 // Record new canvas size.
                 self.resize(w as _, h as _);
+                self.push_event(Event::Resize);
             }
             event::Event::FocusGained => {
-                // No-op, entering the event handler wakes you up.
+                // Entering the event handler wakes you up.
+                self.focused = true;
+                self.push_event(Event::Focus(true));
             }
             event::Event::FocusLost => {
                 // Go to sleep.
                 self.focus_lost = true;
+                self.focused = false;
+                self.push_event(Event::Focus(false));
+            }
+            event::Event::Paste(text) => {
+                self.clipboard = Some(text.clone());
+                self.push_event(Event::Paste(text));
             }
-            event::Event::Paste(_) => {}
         }
     }
 
@@ -373,20 +562,76 @@ impl Runtime {
         // TODO Better error handling when processing crossterm events
 
         // Process immediately available events.
-        // If focus is currently lost, don't poll for events but just enter
-        // the blocking event read until an event comes in and wakes the
-        // program.
-        while self.focus_lost
-            || event::poll(Duration::from_secs(0)).unwrap_or(false)
-        {
+        //
+        // If focus is currently lost, wait according to the background
+        // policy. When blocking, don't poll for events but just enter the
+        // blocking event read until an event comes in and wakes the
+        // program. When throttling, wait out the frame interval for as long
+        // as the terminal is unfocused, regardless of input.
+        if !self.focused {
+            match self.background_policy.frame_interval() {
+                None if self.focus_lost => {
+                    self.process_event(event::read().unwrap())
+                }
+                None => {}
+                Some(interval) => {
+                    let wait = interval - (super::now() - self.last_frame_time);
+                    if wait > 0.0 {
+                        std::thread::sleep(Duration::from_secs_f64(wait));
+                    }
+                }
+            }
+        }
+        self.last_frame_time = super::now();
+
+        while event::poll(Duration::from_secs(0)).unwrap_or(false) {
             self.process_event(event::read().unwrap());
         }
     }
 
+    pub fn set_mouse_capture(&mut self, capture: bool) {
+        if capture == self.mouse_capture {
+            return;
+        }
+        self.mouse_capture = capture;
+        MOUSE_CAPTURE.store(capture, Ordering::SeqCst);
+
+        let mut stdout = std::io::stdout();
+        if capture {
+            queue!(stdout, event::EnableMouseCapture).unwrap();
+        } else {
+            queue!(stdout, event::DisableMouseCapture).unwrap();
+            // Don't leave a drag hanging without the release.
+            self.mouse_state.release_all();
+        }
+        stdout.flush().unwrap();
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        let mut stdout = std::io::stdout();
+        queue!(stdout, terminal::SetTitle(title)).unwrap();
+        stdout.flush().unwrap();
+    }
+
+    /// Copy text to the terminal's clipboard with OSC 52.
+    pub fn clipboard_set(&mut self, text: &str) {
+        let mut stdout = std::io::stdout();
+        let data = general_purpose::STANDARD.encode(text);
+        write!(stdout, "\x1b]52;c;{data}\x1b\\").unwrap();
+        stdout.flush().unwrap();
+        self.clipboard = Some(text.to_owned());
+    }
+
+    fn push_event(&mut self, event: Event) {
+        self.events.push(TimedEvent::new(super::now(), event));
+    }
+
     fn transform_mouse_pos(&self, [x, y]: [i32; 2]) -> [i32; 2] {
-        let [ox, oy] = self.mouse_transform.offset;
-        let [sx, sy] = self.mouse_transform.scale;
-        [(x - ox) * sx, (y - oy) * sy]
+        let MouseTransform { offset, num, den } = self.mouse_transform;
+        [
+            (x - offset[0]) * num[0] / den[0],
+            (y - offset[1]) * num[1] / den[1],
+        ]
     }
 
     fn wake_up(&mut self) {
@@ -396,18 +641,42 @@ impl Runtime {
     }
 }
 
+/// Query the pixel size of a terminal char cell.
+fn cell_size() -> Option<(u32, u32)> {
+    let size = terminal::window_size().ok()?;
+    if size.width == 0
+        || size.height == 0
+        || size.columns == 0
+        || size.rows == 0
+    {
+        return None;
+    }
+    Some((
+        (size.width / size.columns) as u32,
+        (size.height / size.rows) as u32,
+    ))
+}
+
 pub fn cleanup() {
     let mut stdout = std::io::stdout();
+    queue!(stdout, style::ResetColor).unwrap();
+    if KEYBOARD_ENHANCEMENT.swap(false, Ordering::SeqCst) {
+        queue!(stdout, event::PopKeyboardEnhancementFlags).unwrap();
+    }
+    queue!(stdout, cursor::Show).unwrap();
+    if ALTERNATE_SCREEN.swap(false, Ordering::SeqCst) {
+        queue!(stdout, terminal::LeaveAlternateScreen).unwrap();
+    }
     queue!(
         stdout,
-        style::ResetColor,
-        event::PopKeyboardEnhancementFlags,
-        cursor::Show,
-        terminal::LeaveAlternateScreen,
-        event::DisableFocusChange,
-        event::DisableMouseCapture,
+        event::DisableBracketedPaste,
+        event::DisableFocusChange
     )
     .unwrap();
+    if MOUSE_CAPTURE.swap(false, Ordering::SeqCst) {
+        queue!(stdout, event::DisableMouseCapture).unwrap();
+    }
+    super::palette::restore(&mut stdout);
     terminal::disable_raw_mode().unwrap();
     stdout.flush().unwrap();
 }
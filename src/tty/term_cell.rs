@@ -0,0 +1,200 @@
+//! Terminal output cells that can carry full RGB colors.
+use super::color_depth::ColorDepth;
+use crate::{CharCell, Rgba, X256Color};
+
+/// Color sent to the terminal.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum TermColor {
+    /// Indexed color, system colors 0-15 use the terminal's own palette.
+    X256(X256Color),
+    /// Exact 24-bit color.
+    Rgb(Rgba),
+}
+
+impl TermColor {
+    /// Convert a color to the best form the terminal can show.
+    pub fn new(color: Rgba, depth: ColorDepth) -> Self {
+        TermColor::Rgb(color).downgrade(depth, false)
+    }
+
+    pub const BACKGROUND: TermColor = TermColor::X256(X256Color::BACKGROUND);
+    pub const FOREGROUND: TermColor = TermColor::X256(X256Color::FOREGROUND);
+    pub const BOLD_FOREGROUND: TermColor =
+        TermColor::X256(X256Color::BOLD_FOREGROUND);
+
+    /// Return whether this is one of the bright system colors that are shown
+    /// with bold text.
+    pub fn is_bold(self) -> bool {
+        matches!(self, TermColor::X256(c) if (8..16).contains(&c.0))
+    }
+
+    /// Approximate color with one the terminal can show.
+    ///
+    /// Terminals with 8 colors can still show bright foreground colors
+    /// with bold text, so only background colors are limited to 8.
+    /// Monochrome terminals get the system foreground or background color
+    /// depending on the brightness of the color.
+    fn downgrade(self, depth: ColorDepth, background: bool) -> Self {
+        let rgb = match self {
+            TermColor::X256(c) => c.into(),
+            TermColor::Rgb(c) => c,
+        };
+
+        let nearest = |n: usize| {
+            let i = (0..n)
+                .min_by(|&a, &b| {
+                    X256Color::PALETTE[a]
+                        .square_dist(&rgb)
+                        .total_cmp(&X256Color::PALETTE[b].square_dist(&rgb))
+                })
+                .unwrap();
+            TermColor::X256(X256Color(i as u8))
+        };
+
+        match (depth, self) {
+            (ColorDepth::TrueColor, _) => self,
+            (ColorDepth::X256, TermColor::X256(_)) => self,
+            (ColorDepth::X256, TermColor::Rgb(c)) => TermColor::X256(c.into()),
+            (ColorDepth::Ansi16, TermColor::X256(c)) if c.0 < 16 => self,
+            (ColorDepth::Ansi16, _) => nearest(16),
+            (ColorDepth::Ansi8, TermColor::X256(c)) if c.0 < 8 => self,
+            (ColorDepth::Ansi8, TermColor::X256(c)) if c.0 < 16 => {
+                if background {
+                    TermColor::X256(X256Color(c.0 - 8))
+                } else {
+                    self
+                }
+            }
+            (ColorDepth::Ansi8, _) => nearest(if background { 8 } else { 16 }),
+            (ColorDepth::Mono, _) => {
+                let is_light = match self {
+                    TermColor::X256(X256Color::BACKGROUND) => false,
+                    TermColor::X256(
+                        X256Color::FOREGROUND | X256Color::BOLD_FOREGROUND,
+                    ) => true,
+                    _ => rgb.greyscale() >= 0x80,
+                };
+                if is_light {
+                    TermColor::FOREGROUND
+                } else {
+                    TermColor::BACKGROUND
+                }
+            }
+        }
+    }
+
+    /// Select graphic rendition parameters for the color.
+    pub fn sgr(self, background: bool, depth: ColorDepth) -> String {
+        let (base, bright, extended) = if background {
+            (40, 100, 48)
+        } else {
+            (30, 90, 38)
+        };
+
+        match self {
+            TermColor::X256(c) if c.0 < 8 => format!("{}", base + c.0),
+            // Bright foreground on an 8 color terminal, will be shown with
+            // bold.
+            TermColor::X256(c) if c.0 < 16 && depth == ColorDepth::Ansi8 => {
+                format!("{}", base + c.0 - 8)
+            }
+            TermColor::X256(c) if c.0 < 16 => format!("{}", bright + c.0 - 8),
+            TermColor::X256(c) => format!("{extended};5;{}", c.0),
+            TermColor::Rgb(c) => {
+                format!("{extended};2;{};{};{}", c.r, c.g, c.b)
+            }
+        }
+    }
+}
+
+/// Char cell as it's sent to the terminal.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct TermCell {
+    pub c: char,
+    pub foreground: TermColor,
+    pub background: TermColor,
+}
+
+impl TermCell {
+    pub fn new(c: char, foreground: TermColor, background: TermColor) -> Self {
+        TermCell {
+            c,
+            foreground,
+            background,
+        }
+    }
+}
+
+impl TermCell {
+    /// Convert cell colors to ones the terminal can show.
+    pub fn downgrade(self, depth: ColorDepth) -> Self {
+        let mut ret = TermCell {
+            foreground: self.foreground.downgrade(depth, false),
+            background: self.background.downgrade(depth, true),
+            ..self
+        };
+
+        // Keep text readable on monochrome terminals by showing light
+        // backgrounds in reverse video.
+        if depth == ColorDepth::Mono {
+            (ret.foreground, ret.background) =
+                if ret.background == TermColor::FOREGROUND {
+                    (TermColor::BACKGROUND, TermColor::FOREGROUND)
+                } else {
+                    (TermColor::FOREGROUND, TermColor::BACKGROUND)
+                };
+        }
+
+        ret
+    }
+}
+
+impl From<CharCell> for TermCell {
+    fn from(value: CharCell) -> Self {
+        TermCell {
+            c: match char::from_u32(value.c as u32) {
+                Some('\0') => ' ',
+                Some(c) => c,
+                None => char::REPLACEMENT_CHARACTER,
+            },
+            foreground: TermColor::X256(value.foreground),
+            background: TermColor::X256(value.background),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn downgrade() {
+        let cell =
+            TermCell::from(CharCell::new('x', X256Color(196), X256Color(21)));
+
+        let c = cell.downgrade(ColorDepth::Ansi16);
+        assert_eq!(c.foreground, TermColor::X256(X256Color::MAROON));
+        assert_eq!(c.background, TermColor::X256(X256Color::NAVY));
+
+        // Bright colors stay as foreground on 8 color terminals.
+        let c =
+            TermCell::from(CharCell::new('x', X256Color::RED, X256Color::BLUE))
+                .downgrade(ColorDepth::Ansi8);
+        assert_eq!(c.foreground, TermColor::X256(X256Color::RED));
+        assert_eq!(c.background, TermColor::X256(X256Color::NAVY));
+        assert_eq!(c.foreground.sgr(false, ColorDepth::Ansi8), "31");
+        assert_eq!(c.foreground.sgr(false, ColorDepth::Ansi16), "91");
+        assert_eq!(c.background.sgr(true, ColorDepth::Ansi8), "44");
+
+        let c = cell.downgrade(ColorDepth::Mono);
+        assert_eq!(c.foreground, TermColor::FOREGROUND);
+        assert_eq!(c.background, TermColor::BACKGROUND);
+
+        // Light background becomes reverse video.
+        let c =
+            TermCell::from(CharCell::new('x', X256Color(16), X256Color(231)))
+                .downgrade(ColorDepth::Mono);
+        assert_eq!(c.foreground, TermColor::BACKGROUND);
+        assert_eq!(c.background, TermColor::FOREGROUND);
+    }
+}
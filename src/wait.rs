@@ -0,0 +1,109 @@
+//! Awaitable helpers for modal input flows.
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{Click, FrameFuture, KeyTyped};
+
+/// Wait until a later frame has a keypress and return it.
+///
+/// Keypresses from the current frame are not returned, the wait starts
+/// from the next frame.
+pub async fn next_key() -> KeyTyped {
+    loop {
+        FrameFuture::default().await;
+        let key = crate::keypress();
+        if key.is_some() {
+            return key;
+        }
+    }
+}
+
+/// Wait until a later frame has a mouse button release and return the
+/// press that was released.
+///
+/// Like with `next_key`, the wait starts from the next frame.
+pub async fn next_click() -> Click {
+    loop {
+        FrameFuture::default().await;
+        if let Some(click) = crate::mouse_state().any_release() {
+            return click;
+        }
+    }
+}
+
+/// Run a future with a time limit.
+///
+/// Returns `None` if the future hasn't finished after `seconds` have
+/// passed as measured by `navni::now`. The time is checked once per frame,
+/// starting from when the returned future is first polled.
+pub fn timeout<F: Future>(seconds: f64, future: F) -> Timeout<F> {
+    Timeout {
+        future: Box::pin(future),
+        seconds,
+        deadline: None,
+        frame: Default::default(),
+    }
+}
+
+/// Future returned by `timeout`.
+pub struct Timeout<F> {
+    future: Pin<Box<F>>,
+    seconds: f64,
+    deadline: Option<f64>,
+    frame: FrameFuture,
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Option<F::Output>;
+
+    fn poll(
+        mut self: Pin<&mut Self>,
+        context: &mut Context,
+    ) -> Poll<Self::Output> {
+        if let Poll::Ready(ret) = self.future.as_mut().poll(context) {
+            return Poll::Ready(Some(ret));
+        }
+
+        let now = crate::now();
+        let seconds = self.seconds;
+        if now >= *self.deadline.get_or_insert(now + seconds) {
+            return Poll::Ready(None);
+        }
+
+        // Get polled again on the next frame to check the time even if the
+        // inner future doesn't wake up.
+        if Pin::new(&mut self.frame).poll(context).is_ready() {
+            self.frame = Default::default();
+            let _ = Pin::new(&mut self.frame).poll(context);
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(all(test, feature = "headless"))]
+mod test {
+    use super::*;
+    use crate::{BackendType, MouseButton, testing};
+
+    #[test]
+    fn wait_for_input() {
+        crate::select_backend(BackendType::Headless);
+        crate::run("test", async {
+            testing::press("a");
+            assert!(next_key().await.is("a"));
+
+            // 1/60 s frames, so about six frames.
+            let start = crate::now();
+            assert_eq!(timeout(0.1, next_key()).await, None);
+            assert!(crate::now() - start >= 0.1);
+
+            testing::click([1, 2], MouseButton::Left);
+            let click = timeout(1.0, next_click()).await.unwrap();
+            assert_eq!(click.pos, [1, 2]);
+            assert_eq!(click.button, MouseButton::Left);
+        });
+    }
+}
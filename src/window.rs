@@ -0,0 +1,52 @@
+//! Application window settings.
+
+/// Window setup for `navni::run`.
+///
+/// A plain string converts into settings with that title and defaults for
+/// everything else.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct WindowSettings {
+    /// Window title, also set as the terminal title on TTY backends.
+    ///
+    /// This is the only way to title a GUI window, `set_window_title` can't
+    /// change it after the window has been opened.
+    pub title: String,
+    /// Initial window size in pixels, GUI only.
+    pub size: (u32, u32),
+    /// Start in fullscreen mode, GUI only.
+    pub fullscreen: bool,
+    /// Show the operating system mouse cursor over the window, GUI only.
+    pub show_cursor: bool,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            title: "navni".to_owned(),
+            size: (1280, 720),
+            fullscreen: false,
+            show_cursor: true,
+        }
+    }
+}
+
+impl WindowSettings {
+    pub fn new(title: impl Into<String>) -> Self {
+        WindowSettings {
+            title: title.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&str> for WindowSettings {
+    fn from(title: &str) -> Self {
+        WindowSettings::new(title)
+    }
+}
+
+impl From<String> for WindowSettings {
+    fn from(title: String) -> Self {
+        WindowSettings::new(title)
+    }
+}